@@ -1,11 +1,17 @@
+use fission::image::denoise::Denoiser;
+
 fn main() -> anyhow::Result<()> {
     // Parse Args
-    let mut args = std::env::args();
+    let mut args = std::env::args().collect::<Vec<_>>();
+    let denoise = args.iter().any(|arg| arg == "--denoise");
+    args.retain(|arg| arg != "--denoise");
+    let mut args = args.into_iter();
     args.next().unwrap();
 
     let scene_file = match args.next() {
         Some(arg) => arg,
-        None => anyhow::bail!("Usage: cli <scene_description.yaml> [render_progress.state]"),
+        None => anyhow::bail!("Usage: cli <scene_description.yaml> [render_progress.state] \
+                               [--denoise]"),
     };
     let state_file = args.next();
 
@@ -14,6 +20,7 @@ fn main() -> anyhow::Result<()> {
 
     ctrlc::set_handler(move || { running.store(false, std::sync::atomic::Ordering::SeqCst); })?;
 
-    let render_state = renderer.render().iter().last().unwrap();
+    let mut render_state = renderer.render().iter().last().unwrap();
+    if denoise { render_state.denoise.get_or_insert_with(Denoiser::default); }
     fission::save_to_file(scene_file, &render_state)
 }
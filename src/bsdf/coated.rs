@@ -0,0 +1,195 @@
+#[allow(clippy::wildcard_imports)]
+use graphite::*;
+use serde::Deserialize;
+
+use crate::color::{Color, Rgb};
+use crate::image::{bitmap::Bitmap, rect::Rect};
+use crate::texture::{Tex, TexConfig};
+use crate::util::pdf::Pdf;
+
+use super::Bsdf;
+use super::distribution::{Distribution, Model};
+use super::fresnel;
+
+// resolution over uv of the table of light returned by bounces inside the layer
+const BOUNCES_RES: I = 32;
+
+// inner bsdf beneath a smooth or rough dielectric layer of absorbing medium, after
+// Weidlich and Wilkie 2007: light refracts through the interface on the way in and out
+// and is attenuated along both paths through the layer, while the light the interface
+// reflects back inside is returned by the normalization of Mitsuba's rough plastic
+#[derive(Debug, Deserialize)]
+#[serde(from="CoatedConfig")]
+pub struct Coated {
+    inner:   Box<Bsdf>,
+    // roughness of the interface, or none for a perfectly smooth coating
+    alpha:   Option<Tex<F>>,
+    model:   Model,
+    eta:     F,
+    // absorption coefficient times the thickness of the layer
+    sigma_a: Color,
+    // factor for the light bouncing between the interface and the inner bsdf before it
+    // leaves, tabulated over uv as it takes many samples of the inner bsdf to estimate
+    bounces: Bitmap<Color>,
+}
+
+impl Coated {
+    #[inline] pub fn eval(&self, wi: V, wo: V, uv: F2) -> Color {
+        let (ct_i, ct_o) = (Frame::ct(wi), Frame::ct(wo));
+        if ct_i <= 0. || ct_o <= 0. { return Color::ZERO }
+
+        let coat = self.dist(uv).map_or(Color::ZERO, |dist| {
+            let wh = (wi + wo).unit();
+            let fr = fresnel::eval(F3::dot(wh.conv(), wi.conv()), self.eta).0;
+            Color::ONE * (fr * dist.d(wh) * dist.g(wi, wo, wh) * 0.25 / ct_i)
+        });
+
+        let base = match (self.refract_in(wi), self.refract_in(wo)) {
+            (Some(wi_in), Some(wo_in)) => {
+                let ct_o_in = Frame::ct(wo_in);
+                // radiance is compressed into the denser layer and spread out on exit
+                self.inner.eval(wi_in, wo_in, uv) * self.transmittance(wi, wo, wi_in, wo_in, uv)
+                    * (self.eta.sq() * ct_o / ct_o_in)
+            }
+            _ => Color::ZERO,
+        };
+
+        coat + base
+    }
+
+    #[inline] pub fn sample(&self, wi: V, uv: F2, s: F2) -> (Pdf<Color>, V, bool) {
+        let none = (Pdf::new(Color::ZERO, 0.), wi, false);
+        let ct_i = Frame::ct(wi);
+        if ct_i <= 0. { return none }
+
+        let p_coat = self.coat_prob(wi);
+        if s[0] < p_coat {
+            let s = A2(s[0] / p_coat, s[1]);
+            return match self.dist(uv) {
+                None => {
+                    let fr = fresnel::eval(ct_i, self.eta).0;
+                    (Pdf::new(Color::ONE * (fr / p_coat), p_coat),
+                     conv!(Frame::reflect(wi) => V), true)
+                }
+                Some(dist) => {
+                    let wh = dist.sample(wi, s);
+                    self.weighted(wi, wh * (2. * F3::dot(wh.conv(), wi.conv())) - wi, uv)
+                }
+            }
+        }
+
+        let s = A2((s[0] - p_coat) / (1. - p_coat), s[1]);
+        let wi_in = match self.refract_in(wi) { Some(w) => w, None => return none };
+        let (inner, wo_in, spec) = self.inner.sample(wi_in, uv, s);
+        // light reflected back inside by the interface is returned through the transmittance
+        let wo = match self.refract_out(wo_in) { Some(w) => w, None => return none };
+        if spec {
+            let t = self.transmittance(wi, wo, wi_in, wo_in, uv);
+            (Pdf::new(inner.val * t / (1. - p_coat), inner.pdf * (1. - p_coat)), wo, true)
+        } else { self.weighted(wi, wo, uv) }
+    }
+
+    #[inline] pub fn pdf(&self, wi: V, wo: V, uv: F2) -> F {
+        if Frame::ct(wi) <= 0. || Frame::ct(wo) <= 0. { return 0. }
+        let p_coat = self.coat_prob(wi);
+
+        let coat = self.dist(uv).map_or(0., |dist| {
+            let wh = (wi + wo).unit();
+            dist.pdf(wi, wh) * 0.25 / F3::dot(wh.conv(), wo.conv())
+        });
+
+        let base = match (self.refract_in(wi), self.refract_in(wo)) {
+            (Some(wi_in), Some(wo_in)) => self.inner.pdf(wi_in, wo_in, uv)
+                                          * self.eta.sq() * Frame::ct(wo) / Frame::ct(wo_in),
+            _ => 0.,
+        };
+
+        p_coat.mul_add(coat, (1. - p_coat) * base)
+    }
+
+    #[inline] fn weighted(&self, wi: V, wo: V, uv: F2) -> (Pdf<Color>, V, bool) {
+        let p = self.pdf(wi, wo, uv);
+        let color = if p <= 0. { Color::ZERO } else { self.eval(wi, wo, uv) / p };
+        (Pdf::new(color, p), wo, false)
+    }
+
+    #[inline] fn dist(&self, uv: F2) -> Option<Distribution>
+    { self.alpha.as_ref().map(|alpha| Distribution::new(self.model, alpha.eval(uv))) }
+
+    // the coating is chosen by its fresnel reflectance, kept away from 0 and 1
+    #[inline] fn coat_prob(&self, wi: V) -> F
+    { F::clamp(fresnel::eval(Frame::ct(wi), self.eta).0, 0.1, 0.9) }
+
+    // transmission through the interface on both paths and absorption along them, with the
+    // light bouncing between the interface and the inner bsdf until it leaves summed as a
+    // geometric series
+    #[inline] fn transmittance(&self, wi: V, wo: V, wi_in: V, wo_in: V, uv: F2) -> Color {
+        let t = (1. - fresnel::eval(Frame::ct(wi), self.eta).0)
+                * (1. - fresnel::eval(Frame::ct(wo), self.eta).0);
+        absorption(self.sigma_a, wi_in, wo_in) * self.bounces.eval(uv) * t
+    }
+
+    // direction within the layer, as seen from the inner bsdf, refracted from w outside
+    #[inline] fn refract_in(&self, w: V) -> Option<V> {
+        let ct2 = 1. - self.eta.sq() * (1. - Frame::ct(w).sq());
+        if ct2 <= 0. { return None }
+        Some(conv!(A3(self.eta * w[X], self.eta * w[Y], F::sqrt(ct2)) => V))
+    }
+
+    #[inline] fn refract_out(&self, w: V) -> Option<V> {
+        let ct2 = 1. - (1. - Frame::ct(w).sq()) / self.eta.sq();
+        if Frame::ct(w) <= 0. || ct2 <= 0. { return None }
+        Some(conv!(A3(w[X] / self.eta, w[Y] / self.eta, F::sqrt(ct2)) => V))
+    }
+}
+
+#[inline] fn absorption(sigma_a: Color, wi_in: V, wo_in: V) -> Color {
+    let path = Frame::ct(wi_in).inv() + Frame::ct(wo_in).inv();
+    Rgb((sigma_a * path).0.map(|a| F::exp(-a)))
+}
+
+// albedo of the inner bsdf and layer for the diffuse light the interface reflects back
+// down; the inner bsdf may be any, so this is estimated from a fixed set of samples
+fn inner_albedo(inner: &Bsdf, sigma_a: Color, uv: F2) -> Color {
+    const N: I = 4;
+    let sum = (0..N * N).fold(Color::ZERO, |sum, i| {
+        let s = A2(F::of(i % N) + 0.5, F::of(i / N) + 0.5) / F::of(N);
+        let wi_in = conv!(CosineHemisphere::warp(s) => V);
+        let (f, wo_in, _) = inner.sample(wi_in, uv, A2(s[1], s[0]));
+        if f.pdf <= 0. || Frame::ct(wo_in) <= 0. { return sum }
+        sum + f.val * absorption(sigma_a, wi_in, wo_in)
+    });
+    sum / F::of(N * N)
+}
+
+
+#[derive(Debug, Deserialize)]
+struct CoatedConfig {
+    inner:        Box<Bsdf>,
+    ior:          Option<F2>,
+    #[serde(default)]
+    alpha:        Option<TexConfig<F>>,
+    #[serde(default)]
+    distribution: Model,
+    #[serde(default)]
+    sigma_a:      Color,
+    thickness:    Option<F>,
+}
+
+impl From<CoatedConfig> for Coated {
+    fn from(cc: CoatedConfig) -> Self {
+        let eta = fresnel::eta(cc.ior.unwrap_or(A2(1.000_277, 1.5)));
+        let sigma_a = cc.sigma_a * cc.thickness.unwrap_or(1.);
+        // the light reflected back down by the interface returns to it scaled by the albedo,
+        // summing to a geometric series
+        let fdr = fresnel::diffuse_internal(eta);
+        let dims = A2(BOUNCES_RES, BOUNCES_RES);
+        let bounces = Bitmap::from_seq(dims, Rect::at_origin(dims).positions().map(|pos| {
+            let uv = A2(F::of(pos[X]) + 0.5, F::of(pos[Y]) + 0.5) / F::of(BOUNCES_RES);
+            let albedo = inner_albedo(&cc.inner, sigma_a, uv);
+            Rgb(albedo.0.map(|r| F::max(1. - fdr * r, F::EPS).inv()))
+        }));
+        Self { inner: cc.inner, alpha: cc.alpha.map(Tex::from), model: cc.distribution, eta,
+               sigma_a, bounces }
+    }
+}
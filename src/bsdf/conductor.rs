@@ -0,0 +1,129 @@
+use std::convert::TryFrom;
+
+#[allow(clippy::wildcard_imports)]
+use graphite::*;
+use serde::Deserialize;
+
+use crate::color::{Color, Rgb};
+use crate::texture::{Tex, TexConfig};
+use crate::util::pdf::Pdf;
+
+use super::distribution::{Distribution, Model};
+use super::fresnel;
+
+// complex index of refraction, per rgb channel
+#[derive(Debug, Deserialize)]
+#[serde(try_from="IorConfig")]
+pub struct ComplexIor {
+    eta: F3,
+    k:   F3,
+}
+
+impl ComplexIor {
+    #[inline] fn fresnel(&self, ct_i: F) -> Color
+    { Rgb(fresnel::conductor(ct_i, self.eta, self.k)) }
+}
+
+// perfectly smooth metal
+#[derive(Debug, Deserialize)]
+pub struct Conductor {
+    #[serde(flatten)]
+    ior: ComplexIor,
+}
+
+impl Conductor {
+    #[inline] pub fn sample(&self, wi: V) -> (Pdf<Color>, V, bool)
+    { (Pdf::sole(self.ior.fresnel(Frame::ct(wi))), Frame::reflect(wi).conv(), true) }
+}
+
+// metal with microfacet roughness
+#[derive(Debug, Deserialize)]
+#[serde(from="RoughConductorConfig")]
+pub struct RoughConductor {
+    ior:   ComplexIor,
+    alpha: Tex<F>,
+    model: Model,
+}
+
+impl RoughConductor {
+    #[inline] pub fn eval(&self, wi: V, wo: V, uv: F2) -> Color {
+        let ct_i = Frame::ct(wi);
+        if ct_i <= 0. || Frame::ct(wo) <= 0. { return Color::ZERO }
+        let wh = (wi + wo).unit();
+        let fr = self.ior.fresnel(F3::dot(wh.conv(), wi.conv()));
+        let dist = self.dist(uv);
+        fr * (dist.d(wh) * dist.g(wi, wo, wh) * 0.25 / ct_i)
+    }
+
+    #[inline] pub fn sample(&self, wi: V, uv: F2, s: F2) -> (Pdf<Color>, V, bool) {
+        let wh = self.dist(uv).sample(wi, s);
+        let wo = (wh * 2. * F3::dot(wh.conv(), wi.conv()) - wi).unit();
+        let p = self.pdf(wi, wo, uv);
+        let color = if p <= 0. { Color::ZERO } else { self.eval(wi, wo, uv) / p };
+        (Pdf::new(color, p), wo, false)
+    }
+
+    #[inline] pub fn pdf(&self, wi: V, wo: V, uv: F2) -> F {
+        if Frame::ct(wi) <= 0. || Frame::ct(wo) <= 0. { return 0. }
+        let wh = (wi + wo).unit();
+        self.dist(uv).pdf(wi, wh) * 0.25 / F3::dot(wh.conv(), wo.conv())
+    }
+
+    #[inline] fn dist(&self, uv: F2) -> Distribution
+    { Distribution::new(self.model, self.alpha.eval(uv)) }
+}
+
+// (eta, k) of common metals at the r, g and b wavelengths
+fn preset(name: &str) -> Option<(F3, F3)> {
+    Some(match name.to_lowercase().as_str() {
+        "ag" | "silver"   => (A3(0.155265, 0.116723, 0.138342), A3(4.82835, 3.12225, 2.14696)),
+        "al" | "aluminum" => (A3(1.65746, 0.880369, 0.521229),  A3(9.22387, 6.26952, 4.83700)),
+        "au" | "gold"     => (A3(0.143119, 0.374957, 1.44248),  A3(3.98316, 2.38572, 1.60322)),
+        "cr" | "chromium" => (A3(4.36968, 2.91670, 1.65470),    A3(5.20643, 4.23136, 3.75495)),
+        "cu" | "copper"   => (A3(0.200438, 0.924033, 1.10221),  A3(3.91295, 2.45285, 2.14219)),
+        "fe" | "iron"     => (A3(2.95376, 2.93090, 2.58074),    A3(3.12089, 2.94007, 2.82190)),
+        "ni" | "nickel"   => (A3(1.98634, 1.81856, 1.62848),    A3(3.74127, 3.12591, 2.70451)),
+        "ti" | "titanium" => (A3(2.74160, 2.54175, 2.26749),    A3(3.81462, 3.43528, 3.03830)),
+        _ => return None,
+    })
+}
+
+
+#[derive(Debug, Deserialize)]
+struct IorConfig {
+    // name of a preset metal, or an explicit complex ior
+    material: Option<String>,
+    eta:      Option<F3>,
+    k:        Option<F3>,
+}
+
+impl TryFrom<IorConfig> for ComplexIor {
+    type Error = anyhow::Error;
+
+    fn try_from(ic: IorConfig) -> anyhow::Result<Self> {
+        let (eta, k) = match (ic.material, ic.eta, ic.k) {
+            (Some(m), None, None) => preset(&m)
+                .ok_or_else(|| anyhow::anyhow!("unknown conductor material: {}", m))?,
+            (None, Some(eta), Some(k)) => (eta, k),
+            _ => anyhow::bail!("conductors need either a material or both eta and k"),
+        };
+        Ok(Self { eta, k })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct RoughConductorConfig {
+    #[serde(flatten)]
+    ior:          ComplexIor,
+    #[serde(default)]
+    alpha:        Option<TexConfig<F>>,
+    #[serde(default)]
+    distribution: Model,
+}
+
+impl From<RoughConductorConfig> for RoughConductor {
+    fn from(rc: RoughConductorConfig) -> Self {
+        Self { ior: rc.ior, alpha: rc.alpha.map_or(Tex::constant(0.1), Tex::from),
+               model: rc.distribution }
+    }
+}
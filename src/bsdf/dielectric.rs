@@ -3,26 +3,49 @@ use graphite::*;
 use serde::Deserialize;
 
 use crate::color::Color;
+use crate::texture::{Tex, TexConfig};
 use crate::util::pdf::Pdf;
 
 use super::fresnel;
 
 #[derive(Debug, Deserialize)]
+#[serde(from="DielectricConfig")]
 pub struct Dielectric {
-    #[serde(rename="ior", deserialize_with="de_ior_eta")]
-    eta: F,
+    ext: F,
+    int: Tex<F>,
 }
 
 impl Dielectric {
     #[inline]
-    pub fn sample(&self, wi: V, s: F2) -> (Pdf<Color>, V, bool) {
-        let (fr, ctt, eta) = fresnel::eval(Frame::ct(wi), self.eta);
+    pub fn sample(&self, wi: V, uv: F2, s: F2) -> (Pdf<Color>, V, bool) {
+        let (fr, ctt, eta) = fresnel::eval(Frame::ct(wi), self.eta(uv));
         let (wo, p) = if s[0] <= fr { (conv!(Frame::reflect(wi) => V), fr) }
                       else { (conv!(A3(-eta * wi[X], -eta * wi[Y], ctt) => V).unit(), 1. - fr) };
         (Pdf::new(Color::ONE, p), wo, true)
     }
+
+    #[inline] fn eta(&self, uv: F2) -> F { fresnel::eta(A2(self.ext, self.int.eval(uv))) }
+}
+
+
+#[derive(Debug, Deserialize)]
+struct DielectricConfig {
+    ior: IorConfig,
 }
 
-fn de_ior_eta<'de, D>(de: D) -> Result<F, D::Error>
-where D: serde::Deserializer<'de>
-{ F2::deserialize(de).map(fresnel::eta) }
+// (exterior, interior) iors, or the interior ior alone as a value or texture
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum IorConfig {
+    Pair(F2),
+    Interior(TexConfig<F>),
+}
+
+impl From<DielectricConfig> for Dielectric {
+    fn from(dc: DielectricConfig) -> Self {
+        match dc.ior {
+            IorConfig::Pair(A2(ext, int)) => Self { ext, int: Tex::constant(int) },
+            IorConfig::Interior(int) => Self { ext: 1.000_277, int: int.into() },
+        }
+    }
+}
@@ -0,0 +1,111 @@
+#[allow(clippy::wildcard_imports)]
+use graphite::*;
+use serde::Deserialize;
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(rename_all="snake_case")]
+pub enum Model {
+    Beckmann,
+    // trowbridge-reitz
+    Ggx,
+}
+
+impl Default for Model { fn default() -> Self { Self::Beckmann } }
+
+// distribution of microfacet normals in the local shading frame
+#[derive(Debug)]
+pub struct Distribution {
+    model: Model,
+    // roughness along the local x and y axes; beckmann is isotropic in the first
+    alpha: F2,
+}
+
+impl Distribution {
+    #[inline] pub const fn new(model: Model, alpha: F) -> Self
+    { Self { model, alpha: A2(alpha, alpha) } }
+
+    #[inline] pub const fn anisotropic(model: Model, alpha: F2) -> Self { Self { model, alpha } }
+
+    #[inline] pub fn d(&self, wh: V) -> F {
+        match self.model {
+            Model::Beckmann => F::exp(-Frame::t2t(wh) / self.alpha[0].sq()) * F::INV_PI
+                               / (self.alpha[0] * Frame::c2t(wh)).sq(),
+            Model::Ggx => {
+                let h = conv!(wh => F3);
+                if h[Z] <= 0. { return 0. }
+                let A2(ax, ay) = self.alpha;
+                let e = (h[X] / ax).sq() + (h[Y] / ay).sq() + h[Z].sq();
+                F::INV_PI / (ax * ay * e.sq())
+            }
+        }
+    }
+
+    // smith masking of v for microfacets with normal wh
+    #[inline] pub fn g1(&self, v: V, wh: V) -> F {
+        let tt = F::abs(Frame::tt(v));
+        if tt <= 0. { return 1. }
+        if F3::dot(wh.conv(), v.conv()) * Frame::ct(v) <= 0. { return 0. }
+        match self.model {
+            Model::Beckmann => {
+                let a = (self.alpha[0] * tt).inv();
+                if a >= 1.6 { return 1. }
+                a.mul_add(3.535, 2.181 * a.sq())
+                    / a.sq().mul_add(2.577, a.mul_add(2.276, 1.))
+            }
+            Model::Ggx => {
+                let w = conv!(v => F3);
+                let a2t2 = ((self.alpha[0] * w[X]).sq() + (self.alpha[1] * w[Y]).sq()) / w[Z].sq();
+                2. / (1. + F::sqrt(1. + a2t2))
+            }
+        }
+    }
+
+    #[inline] pub fn g(&self, wi: V, wo: V, wh: V) -> F { self.g1(wi, wh) * self.g1(wo, wh) }
+
+    // microfacet normal: beckmann samples the full distribution of normals while ggx
+    // samples only the normals visible from wi
+    #[inline] pub fn sample(&self, wi: V, s: F2) -> V {
+        match self.model {
+            Model::Beckmann => conv!(BeckmannHemisphere::warp(s, self.alpha[0]) => V),
+            Model::Ggx => self.sample_visible(wi, s),
+        }
+    }
+
+    // pdf of sampling the microfacet normal wh from wi
+    #[inline] pub fn pdf(&self, wi: V, wh: V) -> F {
+        match self.model {
+            Model::Beckmann => self.d(wh) * Frame::ct(wh),
+            Model::Ggx => {
+                let ct_i = F::abs(Frame::ct(wi));
+                if ct_i <= 0. { return 0. }
+                self.g1(wi, wh) * F::abs(F3::dot(wi.conv(), wh.conv())) * self.d(wh) / ct_i
+            }
+        }
+    }
+
+    // visible normals sampled as in Heitz 2018, from the hemisphere facing wi
+    #[inline] fn sample_visible(&self, wi: V, s: F2) -> V {
+        let w = conv!(wi => F3);
+        let w = if w[Z] < 0. { -w } else { w };
+        // stretch to the configuration of unit roughness
+        let A2(ax, ay) = self.alpha;
+        let vh = A3(ax * w[X], ay * w[Y], w[Z]);
+        let vh = vh / F::sqrt(F3::dot(vh, vh));
+        let len2 = vh[X].mul_add(vh[X], vh[Y].sq());
+        let t1 = if len2 > 0. { A3(-vh[Y], vh[X], 0.) / F::sqrt(len2) } else { A3(1., 0., 0.) };
+        let t2 = A3(vh[Y] * t1[Z] - vh[Z] * t1[Y],
+                    vh[Z] * t1[X] - vh[X] * t1[Z],
+                    vh[X] * t1[Y] - vh[Y] * t1[X]);
+        // uniform disk warped towards the projected hemisphere
+        let r = F::sqrt(s[0]);
+        let phi = s[1] * F::TWO_PI;
+        let p1 = r * F::cos(phi);
+        let h = 0.5 * (1. + vh[Z]);
+        let p2 = h.mul_add(r * F::sin(phi), (1. - h) * F::sqrt(F::max(0., 1. - p1.sq())));
+        let pz = F::sqrt(F::max(0., 1. - p1.sq() - p2.sq()));
+        let nh = t1 * p1 + t2 * p2 + vh * pz;
+        // unstretch back to the configured roughness
+        let ne = A3(ax * nh[X], ay * nh[Y], F::max(nh[Z], 0.));
+        conv!(ne / F::sqrt(F3::dot(ne, ne)) => V)
+    }
+}
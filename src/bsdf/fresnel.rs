@@ -16,3 +16,33 @@ use graphite::*;
                ct_i.mul_add(s, -ct_t) / ct_i.mul_add(s, ct_t));
     (r.map(F::sq).mean(), ct_t * f, s)
 }
+
+// reflectance of the interface for light arriving uniformly from the side of the surface
+// below, averaged over projected solid angle
+#[inline] pub fn diffuse_internal(eta: F) -> F {
+    const N: I = 256;
+    (0..N).map(|i| {
+        let ct = (F::of(i) + 0.5) / F::of(N);
+        2. * ct * eval(-ct, eta).0
+    }).sum::<F>() / F::of(N)
+}
+
+// per-channel reflectance of a conductor with complex ior eta + ik, from a vacuum
+#[inline] pub fn conductor(ct_i: F, eta: F3, k: F3) -> F3 {
+    let ct = F::clamp(ct_i, 0., 1.);
+    let (c2, s2) = (ct.sq(), 1. - ct.sq());
+    let channel = |i: usize| {
+        let (e2, k2) = (eta[i].sq(), k[i].sq());
+        let t0 = e2 - k2 - s2;
+        let a2b2 = F::sqrt(t0.mul_add(t0, 4. * e2 * k2));
+        let t1 = a2b2 + c2;
+        let a = F::sqrt(0.5 * (a2b2 + t0));
+        let t2 = 2. * ct * a;
+        let rs = (t1 - t2) / (t1 + t2);
+        let t3 = c2.mul_add(a2b2, s2.sq());
+        let t4 = t2 * s2;
+        let rp = rs * (t3 - t4) / (t3 + t4);
+        0.5 * (rp + rs)
+    };
+    A3(channel(0), channel(1), channel(2))
+}
@@ -1,8 +1,15 @@
+mod coated;
+mod conductor;
 mod dielectric;
 mod diffuse;
+mod distribution;
 mod fresnel;
 mod microfacet;
 mod mirror;
+mod principled;
+mod rough_dielectric;
+mod rough_diffuse;
+mod sheen;
 
 #[allow(clippy::wildcard_imports)]
 use graphite::*;
@@ -11,25 +18,46 @@ use serde::Deserialize;
 use crate::color::Color;
 use crate::util::pdf::Pdf;
 
+use coated::Coated;
+use conductor::{Conductor, RoughConductor};
 use dielectric::Dielectric;
 use diffuse::Diffuse;
 use microfacet::Microfacet;
+use principled::Principled;
+use rough_dielectric::RoughDielectric;
+use rough_diffuse::RoughDiffuse;
+use sheen::Sheen;
 
 #[derive(Debug, Deserialize)]
 #[serde(tag="type", rename_all="snake_case")]
 pub enum Bsdf {
+    #[serde(alias="plastic")]
+    Coated(Coated),
+    Conductor(Conductor),
     Dielectric(Dielectric),
     Diffuse(Diffuse),
+    Interface,
     Microfacet(Microfacet),
     Mirror,
+    Principled(Principled),
+    RoughConductor(RoughConductor),
+    RoughDielectric(RoughDielectric),
+    RoughDiffuse(RoughDiffuse),
+    Sheen(Sheen),
 }
 
 impl Bsdf {
     // Bsdf * cos(theta)
     #[inline] pub fn eval(&self, wi: V, wo: V, uv: F2) -> Color {
         match self {
+            Self::Coated(f) => f.eval(wi, wo, uv),
             Self::Diffuse(f) => f.eval(wi, wo, uv),
-            Self::Microfacet(f) => f.eval(wi, wo),
+            Self::Microfacet(f) => f.eval(wi, wo, uv),
+            Self::Principled(f) => f.eval(wi, wo, uv),
+            Self::RoughConductor(f) => f.eval(wi, wo, uv),
+            Self::RoughDielectric(f) => f.eval(wi, wo, uv),
+            Self::RoughDiffuse(f) => f.eval(wi, wo, uv),
+            Self::Sheen(f) => f.eval(wi, wo, uv),
             _ => Color::ZERO,
         }
     }
@@ -38,25 +66,50 @@ impl Bsdf {
     #[inline]
     pub fn sample(&self, wi: V, uv: F2, s: F2) -> (Pdf<Color>, V, bool) {
         match self {
-            Self::Dielectric(f) => f.sample(wi, s),
+            Self::Coated(f) => f.sample(wi, uv, s),
+            Self::Conductor(f) => f.sample(wi),
+            Self::Dielectric(f) => f.sample(wi, uv, s),
             Self::Diffuse(f) => f.sample(uv, s),
-            Self::Microfacet(f) => f.sample(wi, s),
+            Self::Interface => (Pdf::sole(Color::ONE), -wi, true),
+            Self::Microfacet(f) => f.sample(wi, uv, s),
             Self::Mirror => mirror::sample(wi),
+            Self::Principled(f) => f.sample(wi, uv, s),
+            Self::RoughConductor(f) => f.sample(wi, uv, s),
+            Self::RoughDielectric(f) => f.sample(wi, uv, s),
+            Self::RoughDiffuse(f) => f.sample(wi, uv, s),
+            Self::Sheen(f) => f.sample(wi, uv, s),
         }
     }
 
-    #[inline] pub fn pdf(&self, wi: V, wo: V) -> F {
+    #[inline] pub fn pdf(&self, wi: V, wo: V, uv: F2) -> F {
         F::max(match self {
+            Self::Coated(f) => f.pdf(wi, wo, uv),
             Self::Diffuse(_) => Diffuse::pdf(wo),
-            Self::Microfacet(f) => f.pdf(wi, wo),
+            Self::Microfacet(f) => f.pdf(wi, wo, uv),
+            Self::Principled(f) => f.pdf(wi, wo, uv),
+            Self::RoughConductor(f) => f.pdf(wi, wo, uv),
+            Self::RoughDielectric(f) => f.pdf(wi, wo, uv),
+            Self::RoughDiffuse(_) => RoughDiffuse::pdf(wo),
+            Self::Sheen(_) => Sheen::pdf(wo),
             _ => 0.,
         }, 0.)
     }
 
     #[inline] pub const fn is_delta(&self) -> bool
-    { matches!(self, Self::Mirror | Self::Dielectric(_)) }
+    { matches!(self, Self::Conductor(_) | Self::Dielectric(_) | Self::Interface | Self::Mirror) }
+
+    // whether the response changes as the tangent turns about the normal
+    #[inline] pub const fn is_anisotropic(&self) -> bool { matches!(self, Self::Principled(_)) }
+
+    // index-matched boundary that only delimits participating media
+    #[inline] pub const fn is_interface(&self) -> bool { matches!(self, Self::Interface) }
 }
 
+impl From<Coated> for Bsdf { fn from(f: Coated) -> Self { Self::Coated(f) } }
+
+impl From<Conductor> for Bsdf
+{ fn from(f: Conductor) -> Self { Self::Conductor(f) } }
+
 impl From<Dielectric> for Bsdf
 { fn from(f: Dielectric) -> Self { Self::Dielectric(f) } }
 
@@ -65,6 +118,20 @@ impl From<Diffuse> for Bsdf { fn from(f: Diffuse) -> Self { Self::Diffuse(f) } }
 impl From<Microfacet> for Bsdf
 { fn from(f: Microfacet) -> Self { Self::Microfacet(f) } }
 
+impl From<Principled> for Bsdf
+{ fn from(f: Principled) -> Self { Self::Principled(f) } }
+
+impl From<RoughConductor> for Bsdf
+{ fn from(f: RoughConductor) -> Self { Self::RoughConductor(f) } }
+
+impl From<RoughDielectric> for Bsdf
+{ fn from(f: RoughDielectric) -> Self { Self::RoughDielectric(f) } }
+
+impl From<RoughDiffuse> for Bsdf
+{ fn from(f: RoughDiffuse) -> Self { Self::RoughDiffuse(f) } }
+
+impl From<Sheen> for Bsdf { fn from(f: Sheen) -> Self { Self::Sheen(f) } }
+
 impl Zero for Bsdf { const ZERO: Self = Self::Diffuse(Diffuse::ZERO); }
 
 impl Default for Bsdf { fn default() -> Self { Self::ZERO } }
@@ -0,0 +1,340 @@
+#[allow(clippy::wildcard_imports)]
+use graphite::*;
+use serde::Deserialize;
+
+use crate::color::{Color, Rgb};
+use crate::texture::{Tex, TexConfig};
+use crate::util::pdf::Pdf;
+
+use super::distribution::{Distribution, Model};
+use super::fresnel;
+use super::rough_dielectric::RoughDielectric;
+
+// roughness of the clearcoat's masking term, fixed as in the disney brdf
+const CLEARCOAT_G_ALPHA: F = 0.25;
+
+// uber-material after Burley 2012 and 2015, layering a retro-reflective diffuse base
+// with sheen, a ggx specular lobe, rough glass transmission and a gtr1 clearcoat; the
+// anisotropic specular lobe is stretched along the local x axis, which intersections
+// align with dpdu
+#[derive(Debug, Deserialize)]
+#[serde(from="PrincipledConfig")]
+pub struct Principled {
+    base_color:      Tex<Color>,
+    metallic:        Tex<F>,
+    roughness:       Tex<F>,
+    specular:        Tex<F>,
+    specular_tint:   Tex<F>,
+    sheen:           Tex<F>,
+    sheen_tint:      Tex<F>,
+    clearcoat:       Tex<F>,
+    clearcoat_gloss: Tex<F>,
+    transmission:    Tex<F>,
+    anisotropic:     Tex<F>,
+    eta:             F,
+}
+
+// parameters evaluated at a surface point
+struct Lobes {
+    base:      Color,
+    roughness: F,
+    f0:        Color,
+    sheen:     Color,
+    clearcoat: F,
+    cc_alpha:  F,
+    // weights of the diffuse, specular, glass and clearcoat lobes
+    weights:   [F; 4],
+    spec:      Distribution,
+    glass:     RoughDielectric,
+}
+
+impl Principled {
+    #[inline] pub fn eval(&self, wi: V, wo: V, uv: F2) -> Color { self.lobes(uv).eval(wi, wo) }
+
+    #[inline] pub fn sample(&self, wi: V, uv: F2, s: F2) -> (Pdf<Color>, V, bool) {
+        let none = (Pdf::new(Color::ZERO, 0.), wi, false);
+        let lobes = self.lobes(uv);
+        let probs = lobes.probs(wi);
+        // the first dimension picks a lobe and is then rescaled for reuse
+        let (mut s, mut lobe, mut lo) = (s, 0, 0.);
+        while lobe < 3 && (probs[lobe] <= 0. || s[0] >= lo + probs[lobe]) {
+            lo += probs[lobe];
+            lobe += 1;
+        }
+        if probs[lobe] <= 0. { return none }
+        s[0] = F::min((s[0] - lo) / probs[lobe], F::ONE - F::EPS);
+
+        let reflect = |wh: V| wh * (2. * F3::dot(wh.conv(), wi.conv())) - wi;
+        let wo = match lobe {
+            0 => conv!(CosineHemisphere::warp(s) => V),
+            1 => reflect(lobes.spec.sample(wi, s)),
+            2 => {
+                let (glass, wo, _) = lobes.glass.sample(wi, uv, s);
+                if glass.pdf <= 0. { return none }
+                wo
+            }
+            _ => reflect(gtr1_sample(lobes.cc_alpha, s)),
+        };
+        // reflected microfacet samples may still point below the surface
+        if lobe != 2 && Frame::ct(wo) <= 0. { return none }
+        let p = lobes.pdf(wi, wo);
+        let color = if p <= 0. { Color::ZERO } else { lobes.eval(wi, wo) / p };
+        (Pdf::new(color, p), wo, false)
+    }
+
+    #[inline] pub fn pdf(&self, wi: V, wo: V, uv: F2) -> F { self.lobes(uv).pdf(wi, wo) }
+
+    fn lobes(&self, uv: F2) -> Lobes {
+        let base = self.base_color.eval(uv);
+        let lum = base.luminance();
+        let tint = if lum > 0. { base / lum } else { Color::ONE };
+        let metallic = F::clamp(self.metallic.eval(uv), 0., 1.);
+        let transmission = F::clamp(self.transmission.eval(uv), 0., 1.);
+        let roughness = F::clamp(self.roughness.eval(uv), 0., 1.);
+
+        let alpha = F::max(roughness.sq(), 1e-3);
+        let aspect = F::sqrt(1. - 0.9 * F::clamp(self.anisotropic.eval(uv), 0., 1.));
+        let spec = Distribution::anisotropic(
+            Model::Ggx, A2(F::max(alpha / aspect, 1e-3), F::max(alpha * aspect, 1e-3)));
+
+        let specular_tint = lerp(Color::ONE, tint, self.specular_tint.eval(uv));
+        let dielectric_f0 = specular_tint * (0.08 * self.specular.eval(uv));
+        let sheen_tint = lerp(Color::ONE, tint, self.sheen_tint.eval(uv));
+        let clearcoat = F::max(self.clearcoat.eval(uv), 0.);
+        let glass = (1. - metallic) * transmission;
+        Lobes {
+            base, roughness,
+            f0: lerp(dielectric_f0, base, metallic),
+            sheen: sheen_tint * self.sheen.eval(uv),
+            clearcoat,
+            cc_alpha: F::clamp(self.clearcoat_gloss.eval(uv), 0., 1.).mul_add(-0.099, 0.1),
+            weights: [(1. - metallic) * (1. - transmission), 1. - glass, glass,
+                      0.25 * clearcoat],
+            spec,
+            glass: RoughDielectric::new(Tex::constant(alpha), Model::Ggx, self.eta),
+        }
+    }
+}
+
+impl Lobes {
+    fn eval(&self, wi: V, wo: V) -> Color {
+        let (ct_i, ct_o) = (Frame::ct(wi), Frame::ct(wo));
+        let [w_diffuse, w_spec, w_glass, _] = self.weights;
+
+        let glass = if w_glass <= 0. { Color::ZERO } else {
+            // transmitted light takes on the base color
+            let tint = if ct_i * ct_o < 0. { self.base } else { Color::ONE };
+            self.glass.eval(wi, wo, F2::ZERO) * tint * w_glass
+        };
+        if ct_i <= 0. || ct_o <= 0. { return glass }
+
+        let wh = (wi + wo).unit();
+        let cd = F3::dot(wh.conv(), wo.conv());
+
+        let schlick = |ct| self.f0 + (Color::ONE - self.f0) * schlick_weight(ct);
+
+        // the base only sees what the specular interface lets through, on the way in and out
+        let fd90 = (2. * self.roughness).mul_add(cd.sq(), 0.5);
+        let fd = (fd90 - 1.).mul_add(schlick_weight(ct_i), 1.)
+                 * (fd90 - 1.).mul_add(schlick_weight(ct_o), 1.);
+        let base = self.base * (Color::ONE - schlick(ct_i)) * (Color::ONE - schlick(ct_o));
+        let diffuse = (base * (fd * F::INV_PI) + self.sheen * schlick_weight(cd))
+                      * (w_diffuse * ct_o);
+
+        let dh_i = F3::dot(wh.conv(), wi.conv());
+        let fw = schlick_weight(dh_i);
+        let fs = schlick(dh_i);
+        let spec = fs * (self.spec.d(wh) * self.spec.g(wi, wo, wh) * 0.25 / ct_i * w_spec);
+
+        let clearcoat = if self.clearcoat <= 0. { 0. } else {
+            let fc = fw.mul_add(0.96, 0.04);
+            let gc = Distribution::new(Model::Ggx, CLEARCOAT_G_ALPHA).g(wi, wo, wh);
+            0.25 * self.clearcoat * gtr1(Frame::ct(wh), self.cc_alpha) * fc * gc * 0.25 / ct_i
+        };
+
+        diffuse + spec + glass + clearcoat
+    }
+
+    fn pdf(&self, wi: V, wo: V) -> F {
+        let probs = self.probs(wi);
+        let glass = if probs[2] <= 0. { 0. } else { probs[2] * self.glass.pdf(wi, wo, F2::ZERO) };
+        if Frame::ct(wi) <= 0. || Frame::ct(wo) <= 0. { return glass }
+
+        let wh = (wi + wo).unit();
+        let dh_o = F3::dot(wh.conv(), wo.conv());
+        if dh_o <= 0. { return glass }
+        let diffuse = CosineHemisphere::pdf(wo);
+        let spec = self.spec.pdf(wi, wh) * 0.25 / dh_o;
+        let ct_h = Frame::ct(wh);
+        let clearcoat = gtr1(ct_h, self.cc_alpha) * ct_h * 0.25 / dh_o;
+        probs[0].mul_add(diffuse, probs[1].mul_add(spec, probs[3] * clearcoat)) + glass
+    }
+
+    // lobe selection probabilities; only the glass lobe sees light from below
+    fn probs(&self, wi: V) -> [F; 4] {
+        let w = if Frame::ct(wi) > 0. { self.weights } else { [0., 0., self.weights[2], 0.] };
+        let total = w.iter().sum::<F>();
+        if total <= 0. { [0.; 4] } else { w.map(|w| w / total) }
+    }
+}
+
+#[inline] fn lerp(a: Color, b: Color, t: F) -> Color { a * (1. - t) + b * t }
+
+#[inline] fn schlick_weight(ct: F) -> F { F::clamp(1. - ct, 0., 1.).powi(5) }
+
+// generalized trowbridge-reitz distribution with gamma 1, used for the clearcoat
+#[inline] fn gtr1(ct_h: F, alpha: F) -> F {
+    if ct_h <= 0. { return 0. }
+    let a2 = alpha.sq();
+    (a2 - 1.) / (F::PI * F::ln(a2) * ct_h.sq().mul_add(a2 - 1., 1.))
+}
+
+#[inline] fn gtr1_sample(alpha: F, s: F2) -> V {
+    let a2 = alpha.sq();
+    let ct = F::sqrt(F::max(0., (1. - F::powf(a2, 1. - s[0])) / (1. - a2)));
+    let st = F::sqrt(F::max(0., 1. - ct.sq()));
+    let phi = s[1] * F::TWO_PI;
+    conv!(A3(st * F::cos(phi), st * F::sin(phi), ct) => V)
+}
+
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+struct PrincipledConfig {
+    base_color:      TexConfig<Color>,
+    metallic:        TexConfig<F>,
+    roughness:       TexConfig<F>,
+    specular:        TexConfig<F>,
+    specular_tint:   TexConfig<F>,
+    sheen:           TexConfig<F>,
+    sheen_tint:      TexConfig<F>,
+    clearcoat:       TexConfig<F>,
+    clearcoat_gloss: TexConfig<F>,
+    transmission:    TexConfig<F>,
+    anisotropic:     TexConfig<F>,
+    ior:             F,
+}
+
+impl Default for PrincipledConfig {
+    fn default() -> Self {
+        Self { base_color: TexConfig::Val(Rgb(F3::rep(0.8))), metallic: TexConfig::Val(0.),
+               roughness: TexConfig::Val(0.5), specular: TexConfig::Val(0.5),
+               specular_tint: TexConfig::Val(0.), sheen: TexConfig::Val(0.),
+               sheen_tint: TexConfig::Val(0.5), clearcoat: TexConfig::Val(0.),
+               clearcoat_gloss: TexConfig::Val(1.), transmission: TexConfig::Val(0.),
+               anisotropic: TexConfig::Val(0.), ior: 1.5 }
+    }
+}
+
+impl From<PrincipledConfig> for Principled {
+    fn from(pc: PrincipledConfig) -> Self {
+        Self { base_color: pc.base_color.into(), metallic: pc.metallic.into(),
+               roughness: pc.roughness.into(), specular: pc.specular.into(),
+               specular_tint: pc.specular_tint.into(), sheen: pc.sheen.into(),
+               sheen_tint: pc.sheen_tint.into(), clearcoat: pc.clearcoat.into(),
+               clearcoat_gloss: pc.clearcoat_gloss.into(), transmission: pc.transmission.into(),
+               anisotropic: pc.anisotropic.into(), eta: fresnel::eta(A2(1.000_277, pc.ior)) }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::stratified;
+
+    const N: I = 128;
+
+    // white principled material with the given (metallic, roughness, transmission)
+    fn white(metallic: F, roughness: F, transmission: F) -> Principled {
+        Principled::from(PrincipledConfig {
+            base_color: TexConfig::Val(Color::ONE), metallic: TexConfig::Val(metallic),
+            roughness: TexConfig::Val(roughness), transmission: TexConfig::Val(transmission),
+            ..PrincipledConfig::default()
+        })
+    }
+
+    fn direction(theta: F, phi: F) -> V {
+        conv!(A3(F::sin(theta) * F::cos(phi), F::sin(theta) * F::sin(phi), F::cos(theta)) => V)
+    }
+
+    // fraction of the light arriving from wi that is scattered, by the largest channel
+    fn albedo(bsdf: &Principled, wi: V) -> F {
+        let sum = stratified::grid(N).fold(Color::ZERO, |sum, s| {
+            sum + bsdf.sample(wi, F2::ZERO, s).0.val
+        });
+        let Rgb(A3(r, g, b)) = sum / F::of(N * N);
+        F::max(r, F::max(g, b))
+    }
+
+    #[test]
+    fn white_furnace_does_not_gain_energy() {
+        let configs = vec![(0., 0., 0.), (0., 0.5, 0.), (0., 1., 0.), (1., 0.2, 0.),
+                           (1., 0.7, 0.), (0., 0.3, 1.), (0., 0.8, 1.), (0.5, 0.5, 0.5)];
+        for (metallic, roughness, transmission) in configs {
+            let bsdf = white(metallic, roughness, transmission);
+            for &theta in &[0., 0.6, 1.2, 2.] {
+                let a = albedo(&bsdf, direction(theta, 0.));
+                assert!(a <= 1.005, "{:?} at {}: {}", (metallic, roughness, transmission),
+                        theta, a);
+            }
+        }
+    }
+
+    #[test]
+    fn white_furnace_is_lossless_when_smooth() {
+        // microfacet shadowing, the only loss, vanishes for smooth conductors and glass
+        let conductor = white(1., 0.1, 0.);
+        let glass = white(0., 0.1, 1.);
+        for &theta in &[0., 0.6, 1.2] {
+            let a = albedo(&conductor, direction(theta, 0.));
+            assert!(F::abs(a - 1.) < 0.01, "conductor at {}: {}", theta, a);
+        }
+        for &theta in &[0., 0.6, 1.2, 2.5] {
+            let a = albedo(&glass, direction(theta, 0.));
+            assert!(F::abs(a - 1.) < 0.01, "glass at {}: {}", theta, a);
+        }
+    }
+
+    #[test]
+    fn pdf_matches_sampled_directions() {
+        // histogram of sampled directions over equal-area bins of the sphere, against the
+        // pdf integrated over each bin
+        const NZ: I = 8;
+        const NPHI: I = 8;
+        const SUB: I = 16;
+        let bsdf = Principled::from(PrincipledConfig {
+            roughness: TexConfig::Val(0.5), metallic: TexConfig::Val(0.3),
+            transmission: TexConfig::Val(0.5), clearcoat: TexConfig::Val(1.),
+            clearcoat_gloss: TexConfig::Val(0.), anisotropic: TexConfig::Val(0.5),
+            ..PrincipledConfig::default()
+        });
+        let bin = |w: F3| {
+            let z = I::min(F::floori((w[Z] + 1.) * 0.5 * F::of(NZ)), NZ - 1);
+            let phi = F::atan2(w[Y], w[X]).rem_euclid(F::TWO_PI);
+            usize::of(z * NPHI + I::min(F::floori(phi / F::TWO_PI * F::of(NPHI)), NPHI - 1))
+        };
+        let bins = A2(F::of(NZ), F::of(NPHI)).inv();
+
+        for &theta in &[0.7, 2.3] {
+            let wi = direction(theta, 0.4);
+            let mut hist = vec![0.; usize::of(NZ * NPHI)];
+            for s in stratified::grid(N) {
+                let (f, wo, _) = bsdf.sample(wi, F2::ZERO, s);
+                if f.pdf > 0. { hist[bin(conv!(wo => F3))] += F::of(N * N).inv(); }
+            }
+
+            let area = 2. * F::TWO_PI / F::of(NZ * NPHI * SUB * SUB);
+            let diff = (0..NZ * NPHI).map(|b| {
+                let expected = stratified::grid(SUB).map(|s| {
+                    // point of bin b in (z, phi / 2pi), from [0, 1]^2
+                    let A2(z, phi) = (A2(b / NPHI, b % NPHI).map(F::of) + s) * bins;
+                    bsdf.pdf(wi, direction(F::acos(z.mul_add(2., -1.)), phi * F::TWO_PI),
+                             F2::ZERO) * area
+                }).sum::<F>();
+                F::abs(hist[usize::of(b)] - expected)
+            }).sum::<F>();
+            assert!(diff < 0.02, "at {}: {}", theta, diff);
+        }
+    }
+}
@@ -0,0 +1,121 @@
+#[allow(clippy::wildcard_imports)]
+use graphite::*;
+use serde::Deserialize;
+
+use crate::color::Color;
+use crate::sampler;
+use crate::texture::{Tex, TexConfig};
+use crate::util::pdf::Pdf;
+
+use super::distribution::{Distribution, Model};
+use super::fresnel;
+
+// microfacet reflection and refraction of Walter et al. 2007
+#[derive(Debug, Deserialize)]
+#[serde(from="RoughDielectricConfig")]
+pub struct RoughDielectric {
+    alpha: Tex<F>,
+    model: Model,
+    eta:   F,
+}
+
+impl RoughDielectric {
+    #[inline] pub const fn new(alpha: Tex<F>, model: Model, eta: F) -> Self
+    { Self { alpha, model, eta } }
+
+    #[inline] pub fn eval(&self, wi: V, wo: V, uv: F2) -> Color {
+        let (ct_i, ct_o) = (Frame::ct(wi), Frame::ct(wo));
+        if ct_i == 0. || ct_o == 0. { return Color::ZERO }
+        let (wh, etap) = match self.half_vector(wi, wo) {
+            Some(h) => h, None => return Color::ZERO
+        };
+        let (dh_i, dh_o) = (F3::dot(wh.conv(), wi.conv()), F3::dot(wh.conv(), wo.conv()));
+        let fr = fresnel::eval(dh_i, self.eta).0;
+        let dist = self.dist(uv);
+        let dg = dist.d(wh) * dist.g(wi, wo, wh);
+        let f = if ct_i * ct_o > 0. { dg * fr * 0.25 / F::abs(ct_i) } else {
+            let denom = dh_o.mul_add(etap, dh_i).sq() / etap.sq();
+            dg * (1. - fr) * F::abs(dh_i * dh_o / (ct_i * denom))
+        };
+        Color::ONE * f
+    }
+
+    #[inline]
+    pub fn sample(&self, wi: V, uv: F2, s: F2) -> (Pdf<Color>, V, bool) {
+        let dist = self.dist(uv);
+        // directions leaving on the other side of the macro surface than their lobe allows
+        // would be weighted by the density of the other lobe
+        let reflect = |s| {
+            let wh = dist.sample(wi, s);
+            let wo = wh * (2. * F3::dot(wh.conv(), wi.conv())) - wi;
+            if Frame::ct(wi) * Frame::ct(wo) <= 0. { None } else { Some(wo) }
+        };
+        let refract = |s| {
+            let wh = dist.sample(wi, s);
+            let dh_i = F3::dot(wh.conv(), wi.conv());
+            let (fr, ct_t, eta) = fresnel::eval(dh_i, self.eta);
+            // total internal reflection off the sampled microfacet
+            if fr >= 1. { return None }
+            let n = if dh_i > 0. { wh } else { -wh };
+            let wo = wi * -eta + n * F::abs(dh_i).mul_add(eta, -F::abs(ct_t));
+            if Frame::ct(wi) * Frame::ct(wo) >= 0. { None } else { Some(wo) }
+        };
+        let wo = sampler::split_reuse_2d(s, self.reflect_prob(wi), reflect, refract);
+        let (wo, p) = match wo { Some(wo) => (wo, self.pdf(wi, wo, uv)), None => (wi, 0.) };
+        let color = if p <= 0. { Color::ZERO } else { self.eval(wi, wo, uv) / p };
+        (Pdf::new(color, p), wo, false)
+    }
+
+    #[inline] pub fn pdf(&self, wi: V, wo: V, uv: F2) -> F {
+        let (ct_i, ct_o) = (Frame::ct(wi), Frame::ct(wo));
+        if ct_i == 0. || ct_o == 0. { return 0. }
+        let (wh, etap) = match self.half_vector(wi, wo) { Some(h) => h, None => return 0. };
+        let (dh_i, dh_o) = (F3::dot(wh.conv(), wi.conv()), F3::dot(wh.conv(), wo.conv()));
+        let pr = self.reflect_prob(wi);
+        let pdf_h = self.dist(uv).pdf(wi, wh);
+        if ct_i * ct_o > 0. { pdf_h * pr * 0.25 / F::abs(dh_i) }
+        else {
+            let denom = dh_o.mul_add(etap, dh_i).sq() / etap.sq();
+            pdf_h * (1. - pr) * F::abs(dh_o) / denom
+        }
+    }
+
+    // generalized half vector facing +z and the relative ior across the surface, if
+    // neither direction lies on the back of the microfacet
+    #[inline] fn half_vector(&self, wi: V, wo: V) -> Option<(V, F)> {
+        let (ct_i, ct_o) = (Frame::ct(wi), Frame::ct(wo));
+        let etap = if ct_i * ct_o > 0. { 1. } else if ct_i > 0. { self.eta.inv() }
+                   else { self.eta };
+        let wh = wo * etap + wi;
+        if wh.norm2() <= 0. { return None }
+        let wh = wh.unit();
+        let wh = if Frame::ct(wh) < 0. { -wh } else { wh };
+        let (dh_i, dh_o) = (F3::dot(wh.conv(), wi.conv()), F3::dot(wh.conv(), wo.conv()));
+        if dh_i * ct_i <= 0. || dh_o * ct_o <= 0. { return None }
+        Some((wh, etap))
+    }
+
+    #[inline] fn dist(&self, uv: F2) -> Distribution
+    { Distribution::new(self.model, self.alpha.eval(uv)) }
+
+    // reflection is chosen by the fresnel term of the macro surface, kept away from 0 and 1
+    // so that either lobe can still be sampled where the microfacets disagree
+    #[inline] fn reflect_prob(&self, wi: V) -> F
+    { F::clamp(fresnel::eval(Frame::ct(wi), self.eta).0, 0.1, 0.9) }
+}
+
+
+#[derive(Debug, Deserialize)]
+struct RoughDielectricConfig {
+    ior:          F2,
+    alpha:        Option<TexConfig<F>>,
+    #[serde(default)]
+    distribution: Model,
+}
+
+impl From<RoughDielectricConfig> for RoughDielectric {
+    fn from(rc: RoughDielectricConfig) -> Self {
+        Self { alpha: rc.alpha.map_or(Tex::constant(0.1), Tex::from), model: rc.distribution,
+               eta: fresnel::eta(rc.ior) }
+    }
+}
@@ -0,0 +1,56 @@
+#[allow(clippy::wildcard_imports)]
+use graphite::*;
+use serde::Deserialize;
+
+use crate::color::Color;
+use crate::texture::{Tex, TexConfig};
+use crate::util::pdf::Pdf;
+
+// oren-nayar reflectance of v-cavity microfacets with lambertian facets
+#[derive(Debug, Deserialize)]
+#[serde(from="RoughDiffuseConfig")]
+pub struct RoughDiffuse {
+    albedo: Tex<Color>,
+    // standard deviation of the facet angles, in degrees
+    sigma:  Tex<F>,
+}
+
+impl RoughDiffuse {
+    #[inline] pub fn eval(&self, wi: V, wo: V, uv: F2) -> Color {
+        let (ct_i, ct_o) = (Frame::ct(wi), Frame::ct(wo));
+        if ct_i <= 0. || ct_o <= 0. { return Color::ZERO }
+
+        let s2 = self.sigma.eval(uv).to_radians().sq();
+        let a = 1. - s2 / (2. * (s2 + 0.33));
+        let b = 0.45 * s2 / (s2 + 0.09);
+
+        let st = |ct: F| F::sqrt(F::max(0., 1. - ct.sq()));
+        let (st_i, st_o) = (st(ct_i), st(ct_o));
+        let max_cos = if st_i <= 1e-4 || st_o <= 1e-4 { 0. } else {
+            F::max(0., wi[X].mul_add(wo[X], wi[Y] * wo[Y]) / (st_i * st_o))
+        };
+        let (sin_a, tan_b) = if ct_i > ct_o { (st_o, st_i / ct_i) } else { (st_i, st_o / ct_o) };
+        self.albedo.eval(uv) * (F::INV_PI * (b * max_cos).mul_add(sin_a * tan_b, a) * ct_o)
+    }
+
+    #[inline] pub fn sample(&self, wi: V, uv: F2, s: F2) -> (Pdf<Color>, V, bool) {
+        let wo = conv!(CosineHemisphere::warp(s) => V);
+        let p = Self::pdf(wo);
+        let color = if p <= 0. { Color::ZERO } else { self.eval(wi, wo, uv) / p };
+        (Pdf::new(color, p), wo, false)
+    }
+
+    #[inline] pub fn pdf(wo: V) -> F { CosineHemisphere::pdf(wo) }
+}
+
+
+#[derive(Debug, Deserialize)]
+struct RoughDiffuseConfig {
+    albedo: TexConfig<Color>,
+    sigma:  TexConfig<F>,
+}
+
+impl From<RoughDiffuseConfig> for RoughDiffuse {
+    fn from(rc: RoughDiffuseConfig) -> Self
+    { Self { albedo: rc.albedo.into(), sigma: rc.sigma.into() } }
+}
@@ -0,0 +1,52 @@
+#[allow(clippy::wildcard_imports)]
+use graphite::*;
+use serde::Deserialize;
+
+use crate::color::Color;
+use crate::texture::{Tex, TexConfig};
+use crate::util::pdf::Pdf;
+
+// retro-reflective microfiber layer of cloth, with the charlie distribution of
+// Estevez and Kulla 2017 and the visibility term of Neubelt and Pettineo 2013
+#[derive(Debug, Deserialize)]
+#[serde(from="SheenConfig")]
+pub struct Sheen {
+    color:     Tex<Color>,
+    roughness: Tex<F>,
+}
+
+impl Sheen {
+    #[inline] pub fn eval(&self, wi: V, wo: V, uv: F2) -> Color {
+        let (ct_i, ct_o) = (Frame::ct(wi), Frame::ct(wo));
+        if ct_i <= 0. || ct_o <= 0. { return Color::ZERO }
+        let wh = (wi + wo).unit();
+        let inv_a = F::clamp(self.roughness.eval(uv), 1e-3, 1.).inv();
+        let st_h = F::sqrt(F::max(0., 1. - Frame::c2t(wh)));
+        let d = (2. + inv_a) * F::powf(st_h, inv_a) * F::INV_2PI;
+        let v = (4. * (ct_i + ct_o - ct_i * ct_o)).inv();
+        self.color.eval(uv) * (d * v * ct_o)
+    }
+
+    #[inline] pub fn sample(&self, wi: V, uv: F2, s: F2) -> (Pdf<Color>, V, bool) {
+        let wo = conv!(CosineHemisphere::warp(s) => V);
+        let p = Self::pdf(wo);
+        let color = if p <= 0. { Color::ZERO } else { self.eval(wi, wo, uv) / p };
+        (Pdf::new(color, p), wo, false)
+    }
+
+    #[inline] pub fn pdf(wo: V) -> F { CosineHemisphere::pdf(wo) }
+}
+
+
+#[derive(Debug, Deserialize)]
+struct SheenConfig {
+    color:     TexConfig<Color>,
+    roughness: Option<TexConfig<F>>,
+}
+
+impl From<SheenConfig> for Sheen {
+    fn from(sc: SheenConfig) -> Self {
+        Self { color: sc.color.into(),
+               roughness: sc.roughness.map_or(Tex::constant(0.5), Tex::from) }
+    }
+}
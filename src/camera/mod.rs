@@ -14,7 +14,9 @@ pub struct Camera {
         model:      Type,
     pub resolution: I2,
         from_pixel: T2,
+        to_pixel:   T2,
         to_world:   T,
+        area:       F,
 }
 
 #[derive(Debug, Deserialize)]
@@ -27,6 +29,38 @@ impl Camera {
     #[inline]
     pub fn ray_at(&self, point: F2, sampler: &mut Sampler) -> R
     { self.to_world * self.model.ray_at(self.from_pixel * point, sampler) }
+
+    #[inline] pub fn position(&self) -> P { self.to_world * P::ZERO }
+
+    // (importance, pixel position) of a world-space ray leaving the lens
+    #[inline] pub fn we(&self, ray: &R) -> Option<(F, F2)> {
+        let o = self.to_world / ray.o;
+        let d = (self.to_world / ray.d).unit();
+        let ct = d[Z];
+        if ct <= 0. { return None }
+        let pos = self.to_pixel * self.model.image_point(o, d);
+        let res = F2::of(self.resolution);
+        if pos[X] < 0. || pos[Y] < 0. || pos[X] >= res[X] || pos[Y] >= res[Y] { return None }
+        Some(((self.area * self.model.lens_area() * ct.sq().sq()).inv(), pos))
+    }
+
+    // (pdf_pos, pdf_dir) of generating a world-space ray leaving the lens
+    #[inline] pub fn pdf_we(&self, ray: &R) -> (F, F) {
+        let ct = (self.to_world / ray.d).unit()[Z];
+        if ct <= 0. { (0., 0.) }
+        else { (self.model.lens_area().inv(), (self.area * ct * ct.sq()).inv()) }
+    }
+
+    // (importance, solid angle pdf wrt p, lens point, pixel position)
+    #[inline] pub fn sample_wi(&self, p: P, s: F2) -> Option<(F, F, P, F2)> {
+        let lens = self.to_world * self.model.sample_lens(s);
+        let sray = R::p2(p, lens);
+        let n = (self.to_world * conv!(A3(0., 0., 1.) => V)).unit();
+        let ct = F::abs(F3::dot(n.conv(), sray.d.conv()));
+        if ct <= 0. { return None }
+        let (we, pos) = self.we(&R::unbounded(lens, -sray.d))?;
+        Some((we, sray.t.sq() / (ct * self.model.lens_area()), lens, pos))
+    }
 }
 
 impl Type {
@@ -35,6 +69,30 @@ impl Type {
             Self::Perspective(c) => c.ray_at(point, sampler),
         }
     }
+
+    #[inline] fn image_point(&self, o: P, d: V) -> F2 {
+        match self {
+            Self::Perspective(c) => c.image_point(o, d),
+        }
+    }
+
+    #[inline] fn sample_lens(&self, s: F2) -> P {
+        match self {
+            Self::Perspective(c) => c.sample_lens(s),
+        }
+    }
+
+    #[inline] fn lens_area(&self) -> F {
+        match self {
+            Self::Perspective(c) => c.lens_area(),
+        }
+    }
+
+    #[inline] fn image_area(&self, aspect: F) -> F {
+        match self {
+            Self::Perspective(c) => c.image_area(aspect),
+        }
+    }
 }
 
 impl From<Perspective> for Type
@@ -51,9 +109,13 @@ struct CameraConfig {
 
 impl From<CameraConfig> for Camera {
     fn from(cc: CameraConfig) -> Self {
+        let res = F2::of(cc.resolution);
         Self {
-            from_pixel: T2::scale(A2(2., -2.) / F::of(cc.resolution[Y]))
-                      * T2::translate(F2::of(cc.resolution) / -2.),
+            from_pixel: T2::scale(A2(2., -2.) / res[Y])
+                      * T2::translate(res / -2.),
+            to_pixel: T2::translate(res / 2.)
+                    * T2::scale(A2(0.5, -0.5) * res[Y]),
+            area: cc.model.image_area(res[X] / res[Y]),
             resolution: cc.resolution,
             model: cc.model,
             to_world: T::product(cc.transforms.into_iter()) }
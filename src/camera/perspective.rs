@@ -19,12 +19,30 @@ impl Perspective {
     pub fn ray_at(&self, point: F2, sampler: &mut Sampler) -> R {
         let d = F3::a2a(point * self.fov_scale, 1.).conv();
         let ray = R::unbounded(P::ZERO, d);
-        if F::abs(self.lens_radius) < F::EPS { ray } else {
+        if self.is_pinhole() { ray } else {
             let focus_point = ray.at(self.focal_distance / ray.d[Z]);
-            let o = F3::a2a(UniformDisk::warp(sampler.next_2d()) * self.lens_radius, 0.).conv();
+            let o = self.sample_lens(sampler.next_2d());
             R::unbounded(o, focus_point - o)
         }
     }
+
+    // inverse of ray_at: image plane point of a camera-space ray
+    #[inline] pub fn image_point(&self, o: P, d: V) -> F2 {
+        let t = if self.is_pinhole() { d[Z].inv() } else { self.focal_distance / d[Z] };
+        let p = o + d * t;
+        A2(p[X], p[Y]) / (p[Z] * self.fov_scale)
+    }
+
+    #[inline] pub fn sample_lens(&self, s: F2) -> P
+    { F3::a2a(UniformDisk::warp(s) * self.lens_radius, 0.).conv() }
+
+    #[inline] pub fn lens_area(&self) -> F
+    { if self.is_pinhole() { 1. } else { F::PI * self.lens_radius.sq() } }
+
+    // area of the image plane at unit distance
+    #[inline] pub fn image_area(&self, aspect: F) -> F { 4. * aspect * self.fov_scale.sq() }
+
+    #[inline] fn is_pinhole(&self) -> bool { F::abs(self.lens_radius) < F::EPS }
 }
 
 
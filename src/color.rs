@@ -21,6 +21,13 @@ impl Rgb {
 
     const SENSITIVITIES: F3 = A3(0.212671, 0.715160, 0.072169);
     #[inline] pub fn luminance(self) -> F { F3::dot(self.0, Self::SENSITIVITIES) }
+
+    // linear srgb from cie xyz
+    #[inline] pub fn from_xyz(xyz: F3) -> Self {
+        Self(A3(F3::dot(A3( 3.240_454, -1.537_138, -0.498_531), xyz),
+                F3::dot(A3(-0.969_266,  1.876_011,  0.041_556), xyz),
+                F3::dot(A3( 0.055_643, -0.204_026,  1.057_225), xyz)))
+    }
 }
 
 op!(Neg::neg, *Rgb);
@@ -68,8 +75,36 @@ impl Conv<Rgb> for Rgba<u8>
 /* Convert Rgb to Types */
 impl Conv<F3> for Rgb { #[inline] fn conv(self) -> F3 { self.0 } }
 
+// grayscale values, as loaded into scalar textures
+impl Conv<F> for Rgb { #[inline] fn conv(self) -> F { self.luminance() } }
+
 impl Conv<Rgb> for Rgb { #[inline] fn conv(self) -> Self { self } }
 
 
-#[inline] fn gamma_correct_inv(f: F) -> F
+#[inline] pub fn gamma_correct_inv(f: F) -> F
 { if f <= 0.04045 { f / 12.92 } else { ((f + 0.055) / 1.055).powf(2.4) } }
+
+// linear srgb color of a blackbody at the given temperature in kelvin, at unit luminance
+pub fn blackbody(temperature: F) -> Rgb {
+    // second radiation constant in micrometre kelvin
+    const C2: F = 14_388.;
+    if temperature <= 0. { return Rgb::ZERO }
+    let xyz = (0..=80).map(|i| {
+        let l = F::of(i).mul_add(5., 380.);
+        let um = l * 1e-3;
+        let planck = um.powi(-5) / (F::exp(C2 / (um * temperature)) - 1.);
+        cie_xyz(l) * planck
+    }).sum::<F3>();
+    let rgb = Rgb(Rgb::from_xyz(xyz).0.map(|c| F::max(c, 0.)));
+    let lum = rgb.luminance();
+    if lum > 0. { rgb / lum } else { Rgb::ZERO }
+}
+
+// analytic fit of the cie 1931 matching functions by Wyman et al. 2013
+#[inline] fn cie_xyz(l: F) -> F3 {
+    let g = |mu: F, s1: F, s2: F| (-0.5 * ((l - mu) / if l < mu { s1 } else { s2 }).sq()).exp();
+    A3(F::mul_add(1.056, g(599.8, 37.9, 31.0),
+                  F::mul_add(0.362, g(442.0, 16.0, 26.7), -0.065 * g(501.1, 20.4, 26.2))),
+       F::mul_add(0.821, g(568.8, 46.9, 40.5), 0.286 * g(530.9, 16.3, 31.1)),
+       F::mul_add(1.217, g(437.0, 11.8, 36.0), 0.681 * g(459.0, 26.0, 13.8)))
+}
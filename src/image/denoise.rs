@@ -0,0 +1,99 @@
+#[allow(clippy::wildcard_imports)]
+use graphite::*;
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use serde::{Deserialize, Serialize};
+
+use crate::color::Color;
+
+use super::{Image, Layer, bitmap::Bitmap, pixel::Pixel};
+
+// joint non-local-means filter guided by per-pixel variance and, when rendered,
+// the albedo and normal aov layers
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Denoiser {
+    radius:       I,
+    patch:        I,
+    k:            F,
+    sigma_albedo: F,
+    sigma_normal: F,
+}
+
+struct Buffers<'a> {
+    color:  Bitmap<Color>,
+    var:    Bitmap<F>,
+    albedo: Option<&'a Image>,
+    normal: Option<&'a Image>,
+}
+
+impl Denoiser {
+    pub fn denoise(&self, img: &Image, layers: &[Layer]) -> Image {
+        let feature = |name| layers.iter().find(|l| l.name == name).map(|l| &l.img);
+        let dims = img.rect.dims;
+        let buffers = Buffers {
+            color:  Bitmap::from_seq(dims, img.pixels().map(|&p| p.conv())),
+            var:    Bitmap::from_seq(dims, img.pixels().map(|p| {
+                        let v = p.variance();
+                        if v.is_finite() { v } else { 0. }
+                    })),
+            albedo: feature("albedo"),
+            normal: feature("normal"),
+        };
+
+        let pixels = img.rect.positions().collect::<Vec<_>>().into_par_iter()
+                        .map(|p| self.filter(&buffers, p)).collect::<Vec<_>>();
+        Bitmap::from_seq(dims, pixels)
+    }
+
+    fn filter(&self, b: &Buffers, p: I2) -> Pixel {
+        let dims = b.color.rect.dims;
+        let mut sum = Color::ZERO;
+        let mut w_sum = 0.;
+        for dy in -self.radius..=self.radius {
+            for dx in -self.radius..=self.radius {
+                let q = p + A2(dx, dy);
+                if !in_bounds(q, dims) { continue }
+                let w = self.patch_weight(b, p, q)
+                      * feature_weight(b.albedo, p, q, self.sigma_albedo)
+                      * feature_weight(b.normal, p, q, self.sigma_normal);
+                sum += b.color[q] * w;
+                w_sum += w;
+            }
+        }
+        Pixel::new(if w_sum > 0. { sum / w_sum } else { b.color[p] }, 1.)
+    }
+
+    // variance-normalized patch distance of Rousselle et al. 2012
+    fn patch_weight(&self, b: &Buffers, p: I2, q: I2) -> F {
+        let dims = b.color.rect.dims;
+        let mut d = 0.;
+        let mut n = 0.;
+        for dy in -self.patch..=self.patch {
+            for dx in -self.patch..=self.patch {
+                let o = A2(dx, dy);
+                let (pp, qq) = (p + o, q + o);
+                if !in_bounds(pp, dims) || !in_bounds(qq, dims) { continue }
+                let (vp, vq) = (b.var[pp], b.var[qq]);
+                let diff = (b.color[pp] - b.color[qq]).0.map(F::sq).mean();
+                d += (diff - (vp + F::min(vp, vq))) / (1e-10 + self.k.sq() * (vp + vq));
+                n += 1.;
+            }
+        }
+        if n > 0. { F::exp(-F::max(0., d / n)) } else { 1. }
+    }
+}
+
+impl Default for Denoiser {
+    fn default() -> Self
+    { Self { radius: 5, patch: 1, k: 0.45, sigma_albedo: 0.1, sigma_normal: 0.3 } }
+}
+
+#[inline] fn feature_weight(feature: Option<&Image>, p: I2, q: I2, sigma: F) -> F {
+    feature.map_or(1., |f| {
+        let (fp, fq): (Color, Color) = (f[p].conv(), f[q].conv());
+        F::exp(-(fp - fq).0.map(F::sq).reduce(|a, b| a + b) / (2. * sigma.sq()))
+    })
+}
+
+#[inline] fn in_bounds(p: I2, dims: I2) -> bool
+{ p[X] >= 0 && p[Y] >= 0 && p[X] < dims[X] && p[Y] < dims[Y] }
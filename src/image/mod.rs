@@ -1,10 +1,12 @@
 pub mod bitmap;
+pub mod denoise;
 pub mod pixel;
 pub mod rect;
 
-use exr::prelude::write_rgb_f32_file;
+use exr::prelude::{AnyChannel, AnyChannels, Encoding, FlatSamples, WritableImage};
 #[allow(clippy::wildcard_imports)]
 use graphite::*;
+use serde::{Deserialize, Serialize};
 
 use bitmap::Bitmap;
 use pixel::Pixel;
@@ -15,18 +17,40 @@ const BLOCK_SIZE: I2 = A2(32, 32);
 
 pub type Image = Bitmap<Pixel>;
 
+// auxiliary image written alongside the main one, with names for its leading channels
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Layer {
+    pub name:     String,
+    pub channels: Vec<String>,
+    pub img:      Image,
+}
+
 macro_rules! conv {
     ($expr:expr) => { $expr };
     ($expr:expr => $t:ty $(=> $tt:ty)*) => { conv!(<$t>::of($expr) $(=> $tt)*) };
 }
 
 impl Image {
-    pub fn save_exr(&self, filename: &str) -> anyhow::Result<()> {
+    // the image itself as plain rgb channels, and each layer as channels prefixed by its name
+    pub fn save_exr(&self, filename: &str, layers: &[Layer]) -> anyhow::Result<()> {
         let dims = conv!(self.rect.dims => A2<usize> => (usize, usize));
-        write_rgb_f32_file(filename, dims, |x, y| {
-            let idx = conv!(A2(x, y) => I2);
-            conv!(self[idx] => Color => Rgb => F3 => A3<f32> => (f32, f32, f32))
-        })?;
+        let rgb = ["R", "G", "B"].iter().map(|c| (*c).to_string()).collect::<Vec<_>>();
+        let names = layers.iter().map(|l| {
+            l.channels.iter().map(|c| format!("{}.{}", l.name, c)).collect::<Vec<_>>()
+        }).collect::<Vec<_>>();
+        let channels = Some((rgb.as_slice(), self)).into_iter()
+            .chain(names.iter().zip(layers).map(|(names, l)| (names.as_slice(), &l.img)))
+            .flat_map(|(names, img)| names.iter().enumerate().map(move |(i, c)| {
+                let samples = img.pixels().map(|p| {
+                    let A3(r, g, b) = conv!(*p => Color => Rgb => F3 => A3<f32>);
+                    [r, g, b][i]
+                }).collect();
+                AnyChannel::new(c.as_str(), FlatSamples::F32(samples))
+            })).collect();
+
+        exr::prelude::Image::from_encoded_channels(dims, Encoding::FAST_LOSSLESS,
+                                                   AnyChannels::sort(channels))
+            .write().to_file(filename)?;
         Ok(())
     }
 }
@@ -11,20 +11,46 @@ use crate::color::{Color, Rgb};
 pub struct Pixel {
     val: Color,
     w:   F,
+    // sum of squared sample luminances
+    sq:  F,
+}
+
+impl Pixel {
+    #[inline] pub const fn new(val: Color, w: F) -> Self { Self { val, w, sq: F::ZERO } }
+
+    // contribution that does not count as a sample of the pixel
+    #[inline] pub const fn splat(val: Color) -> Self { Self::new(val, F::ZERO) }
+
+    // variance of the mean luminance
+    #[inline] pub fn variance(&self) -> F {
+        if self.w <= 1. { return F::POS_INF }
+        let mean = self.val.luminance() / self.w;
+        F::max(0., self.sq / self.w - mean.sq()) / (self.w - 1.)
+    }
+
+    // standard error relative to the mean luminance
+    #[inline] pub fn rel_error(&self) -> F {
+        let mean = self.val.luminance() / self.w;
+        let var = self.variance();
+        if var == 0. { 0. } else { F::sqrt(var) / F::max(mean, 1e-3) }
+    }
 }
 
 impl Zero for Pixel
-{ const ZERO: Self = Self { val: Color::ZERO, w: F::ZERO }; }
+{ const ZERO: Self = Self::new(Color::ZERO, F::ZERO); }
 
 impl AddAssign for Pixel {
     #[inline] fn add_assign(&mut self, pixel: Self) {
         self.val += pixel.val;
         self.w += pixel.w;
+        self.sq += pixel.sq;
     }
 }
 
-impl AddAssign<Color> for Pixel
-{ #[inline] fn add_assign(&mut self, color: Color) { *self += Self { val: color, w: 1. }; } }
+impl AddAssign<Color> for Pixel {
+    #[inline] fn add_assign(&mut self, color: Color)
+    { *self += Self { val: color, w: 1., sq: color.luminance().sq() }; }
+}
 
 impl Conv<Color> for Pixel
 { #[inline] fn conv(self) -> Rgb { if self.w == 0. { Color::ZERO } else { self.val / self.w } } }
@@ -24,6 +24,7 @@
 pub mod color;
 pub mod image;
     mod light;
+    mod medium;
 pub mod renderer;
     mod sampler;
     mod scene;
@@ -77,7 +78,14 @@ where P: AsRef<Path> {
         let img_save_path = scene_file.with_extension("exr");
         let msg = format!("Saving rendered image ({})", img_save_path.display());
         let _p = Progress::indeterminate(&msg);
-        state.img.save_exr(img_save_path.to_str().unwrap())?;
+        state.img.save_exr(img_save_path.to_str().unwrap(), &state.aovs)?;
+    }
+    if let Some(denoiser) = &state.denoise {
+        let img_save_path = scene_file.with_extension("denoised.exr");
+        let msg = format!("Saving denoised image ({})", img_save_path.display());
+        let _p = Progress::indeterminate(&msg);
+        denoiser.denoise(&state.img, &state.aovs)
+                .save_exr(img_save_path.to_str().unwrap(), &[])?;
     }
     {
         let state_save_path = scene_file.with_extension("state");
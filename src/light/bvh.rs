@@ -0,0 +1,244 @@
+use std::sync::Arc;
+
+#[allow(clippy::wildcard_imports)]
+use graphite::*;
+
+use crate::shape::{Intersectable, intersection::Its};
+
+use super::Light;
+
+// spatial and directional bounds of the emission of a set of lights
+#[derive(Clone, Copy, Debug)]
+struct LightBounds {
+    lo:    F3,
+    hi:    F3,
+    // cone of emitting normals: axis and cosine of its half-angle
+    w:     F3,
+    cos_o: F,
+    // cosine of the emission spread beyond the normal cone
+    cos_e: F,
+    phi:   F,
+}
+
+#[derive(Debug)]
+enum Node {
+    Leaf(usize),
+    Interior([usize; 2]),
+}
+
+// light bounding volume hierarchy: lights are chosen by descending the tree with
+// probabilities proportional to each child's estimated contribution at the shading point
+#[derive(Debug)]
+pub struct LightBvh {
+    nodes:    Vec<(LightBounds, Node)>,
+    // per light: path from the root as a bit trail (lsb first) and its depth
+    trails:   Box<[Option<(u64, u32)>]>,
+    infinite: Vec<usize>,
+}
+
+impl LightBvh {
+    pub fn new(lights: &[Arc<Light>], bsphere: (P, F)) -> Self {
+        let mut infinite = vec![];
+        let mut bounded = vec![];
+        for (i, light) in lights.iter().enumerate() {
+            match bounds(light, bsphere) {
+                None => infinite.push(i),
+                Some(b) if b.phi > 0. => bounded.push((i, b)),
+                Some(_) => (),
+            }
+        }
+        let mut bvh = Self { nodes: vec![], trails: vec![None; lights.len()].into_boxed_slice(),
+                             infinite };
+        if !bounded.is_empty() { bvh.build(&mut bounded, 0, 0); }
+        bvh
+    }
+
+    fn build(&mut self, lights: &mut [(usize, LightBounds)], trail: u64, depth: u32) -> usize {
+        let idx = self.nodes.len();
+        if let [(light, b)] = lights {
+            self.nodes.push((*b, Node::Leaf(*light)));
+            self.trails[*light] = Some((trail, depth));
+            return idx
+        }
+
+        let b = lights.iter().map(|l| l.1).reduce(LightBounds::union).unwrap();
+        self.nodes.push((b, Node::Interior([0, 0])));
+
+        // split at the median centroid along the widest axis of the centroids
+        let (clo, chi) = lights.iter().fold((F3::rep(F::POS_INF), F3::rep(-F::POS_INF)),
+            |(lo, hi), l| (lo.zip(l.1.center(), F::min), hi.zip(l.1.center(), F::max)));
+        let ext = chi - clo;
+        let dim = if ext[0] >= ext[1] && ext[0] >= ext[2] { 0 }
+                  else if ext[1] >= ext[2] { 1 } else { 2 };
+        lights.sort_by(|a, b| a.1.center()[dim].partial_cmp(&b.1.center()[dim]).unwrap());
+        let mid = lights.len() / 2;
+        let (l, r) = lights.split_at_mut(mid);
+
+        let left = self.build(l, trail, depth + 1);
+        let right = self.build(r, trail | (1 << depth), depth + 1);
+        self.nodes[idx].1 = Node::Interior([left, right]);
+        idx
+    }
+
+    // (light index, probability of choosing it)
+    #[inline] pub fn sample(&self, its: &Its, s: &mut F) -> Option<(usize, F)> {
+        let p_inf = self.p_infinite();
+        if *s < p_inf {
+            *s /= p_inf;
+            let n = self.infinite.len();
+            let i = usize::min(usize::of(F::floori(*s * F::of(I::of(n)))), n - 1);
+            *s = *s * F::of(I::of(n)) - F::of(I::of(i));
+            return Some((self.infinite[i], p_inf / F::of(I::of(n))))
+        }
+        if self.nodes.is_empty() { return None }
+
+        *s = F::min((*s - p_inf) / (1. - p_inf), 1. - F::EPS);
+        let mut pmf = 1. - p_inf;
+        let mut node = 0;
+        loop {
+            match &self.nodes[node] {
+                (b, Node::Leaf(light)) =>
+                    return if b.importance(its) > 0. { Some((*light, pmf)) } else { None },
+                (_, Node::Interior(children)) => {
+                    let imp = children.map(|c| self.nodes[c].0.importance(its));
+                    let total = imp[0] + imp[1];
+                    if total <= 0. { return None }
+                    let p0 = imp[0] / total;
+                    if *s < p0 { *s /= p0; pmf *= p0; node = children[0]; }
+                    else { *s = (*s - p0) / (1. - p0); pmf *= 1. - p0; node = children[1]; }
+                }
+            }
+        }
+    }
+
+    #[inline] pub fn pmf(&self, its: &Its, light: usize) -> F {
+        let p_inf = self.p_infinite();
+        let (trail, depth) = match self.trails[light] {
+            None => return if self.infinite.contains(&light) {
+                               p_inf / F::of(I::of(self.infinite.len()))
+                           } else { 0. },
+            Some(t) => t,
+        };
+
+        let mut pmf = 1. - p_inf;
+        let mut node = 0;
+        for d in 0..depth {
+            if let (_, Node::Interior(children)) = &self.nodes[node] {
+                let imp = children.map(|c| self.nodes[c].0.importance(its));
+                let total = imp[0] + imp[1];
+                if total <= 0. { return 0. }
+                let branch = usize::from((trail >> d) & 1 == 1);
+                pmf *= imp[branch] / total;
+                node = children[branch];
+            }
+        }
+        if self.nodes[node].0.importance(its) > 0. { pmf } else { 0. }
+    }
+
+    #[inline] fn p_infinite(&self) -> F {
+        let n = F::of(I::of(self.infinite.len()));
+        if self.nodes.is_empty() { F::ONE.min(n) } else { n / (n + 1.) }
+    }
+}
+
+impl LightBounds {
+    #[inline] fn center(&self) -> F3 { (self.lo + self.hi) * 0.5 }
+
+    fn union(a: Self, b: Self) -> Self {
+        let (w, cos_o) = cone_union((a.w, a.cos_o), (b.w, b.cos_o));
+        Self { lo: a.lo.zip(b.lo, F::min), hi: a.hi.zip(b.hi, F::max), w, cos_o,
+               cos_e: F::min(a.cos_e, b.cos_e), phi: a.phi + b.phi }
+    }
+
+    // conservative estimate of the contribution at a point (pbrt-v4)
+    fn importance(&self, its: &Its) -> F {
+        let p = conv!(its.p => F3);
+        let pc = self.center();
+        let half_diag = F::sqrt(F3::dot(self.hi - self.lo, self.hi - self.lo)) * 0.5;
+        let d2 = F3::dot(p - pc, p - pc);
+        if d2 <= 0. { return self.phi }
+        let wi = (p - pc) / F::sqrt(d2);
+
+        let cos_w = F3::dot(self.w, wi);
+        let sin_w = safe_sqrt(1. - cos_w.sq());
+
+        // half-angle subtended by the bounds from p
+        let cos_b = if d2 < half_diag.sq() { -1. } else { safe_sqrt(1. - half_diag.sq() / d2) };
+        let sin_b = safe_sqrt(1. - cos_b.sq());
+
+        let sin_o = safe_sqrt(1. - self.cos_o.sq());
+        let (cos_x, sin_x) = (cos_sub_clamped(sin_w, cos_w, sin_o, self.cos_o),
+                              sin_sub_clamped(sin_w, cos_w, sin_o, self.cos_o));
+        let cos_p = cos_sub_clamped(sin_x, cos_x, sin_b, cos_b);
+        if cos_p <= self.cos_e { return 0. }
+
+        let mut importance = self.phi * cos_p / F::max(d2, half_diag);
+        let n = conv!(its.n => F3);
+        if n != F3::ZERO {
+            let cos_i = F::abs(F3::dot(wi, n) / F::sqrt(F3::dot(n, n)));
+            let sin_i = safe_sqrt(1. - cos_i.sq());
+            importance *= cos_sub_clamped(sin_i, cos_i, sin_b, cos_b);
+        }
+        F::max(importance, 0.)
+    }
+}
+
+fn bounds(light: &Light, bsphere: (P, F)) -> Option<LightBounds> {
+    let phi = light.power(bsphere);
+    match light {
+        Light::Area(shape) => {
+            let bbox = shape.bbox();
+            let (c, e) = (conv!(bbox.center() => F3), bbox.extents());
+            let h = A3(e[X], e[Y], e[Z]) * 0.5;
+            // two-sided emitters radiate in every direction
+            let (w, cos_o) = if shape.emits_two_sided() { (A3(0., 0., 1.), -1.) }
+                             else { shape.normal_cone() };
+            Some(LightBounds { lo: c - h, hi: c + h, w, cos_o, cos_e: 0., phi })
+        }
+        Light::Directional(_) | Light::Infinite(_) | Light::Sky(_) | Light::Sun(_) => None,
+        Light::Point(l) => {
+            let p = conv!(l.position() => F3);
+            Some(LightBounds { lo: p, hi: p, w: A3(0., 0., 1.), cos_o: -1., cos_e: 0., phi })
+        }
+        Light::Spot(l) => {
+            let p = conv!(l.position() => F3);
+            let (cos_falloff, cos_cutoff) = l.cone();
+            let cos_e = F::cos(F::acos(cos_cutoff) - F::acos(cos_falloff));
+            Some(LightBounds { lo: p, hi: p, w: conv!(l.direction() => F3),
+                               cos_o: cos_falloff, cos_e, phi })
+        }
+    }
+}
+
+// smallest cone containing both cones
+fn cone_union(a: (F3, F), b: (F3, F)) -> (F3, F) {
+    const FULL: (F3, F) = (A3(0., 0., 1.), -1.);
+    let (ta, tb) = (F::acos(F::clamp(a.1, -1., 1.)), F::acos(F::clamp(b.1, -1., 1.)));
+    let td = F::acos(F::clamp(F3::dot(a.0, b.0), -1., 1.));
+    if F::min(td + tb, F::PI) <= ta { return a }
+    if F::min(td + ta, F::PI) <= tb { return b }
+
+    let to = (ta + td + tb) * 0.5;
+    if to >= F::PI { return FULL }
+    let axis = cross(a.0, b.0);
+    let len = F::sqrt(F3::dot(axis, axis));
+    if len <= 0. { return FULL }
+
+    // rotate a's axis towards b's by to - ta
+    let (k, tr) = (axis / len, to - ta);
+    let w = a.0 * F::cos(tr) + cross(k, a.0) * F::sin(tr)
+          + k * (F3::dot(k, a.0) * (1. - F::cos(tr)));
+    (w, F::cos(to))
+}
+
+#[inline] fn cross(a: F3, b: F3) -> F3
+{ A3(a[1] * b[2] - a[2] * b[1], a[2] * b[0] - a[0] * b[2], a[0] * b[1] - a[1] * b[0]) }
+
+#[inline] fn safe_sqrt(f: F) -> F { F::sqrt(F::max(f, 0.)) }
+
+// cos(max(0, a - b)) and sin(max(0, a - b)) from sines and cosines of a and b
+#[inline] fn cos_sub_clamped(sin_a: F, cos_a: F, sin_b: F, cos_b: F) -> F
+{ if cos_a > cos_b { 1. } else { cos_a.mul_add(cos_b, sin_a * sin_b) } }
+
+#[inline] fn sin_sub_clamped(sin_a: F, cos_a: F, sin_b: F, cos_b: F) -> F
+{ if cos_a > cos_b { 0. } else { sin_a.mul_add(cos_b, -cos_a * sin_b) } }
@@ -0,0 +1,57 @@
+#[allow(clippy::wildcard_imports)]
+use graphite::*;
+use serde::Deserialize;
+
+use crate::color::Color;
+use crate::shape::intersection::Its;
+use crate::util::pdf::Pdf;
+
+#[derive(Debug, Deserialize)]
+#[serde(from="DirectionalConfig")]
+pub struct Directional {
+        irradiance:  Color,
+        // direction the light travels in
+        dir:         V,
+    pub light_group: Option<String>,
+        // scene bounding sphere, set once the scene is built
+        bsphere:     (P, F),
+}
+
+impl Directional {
+    #[inline] pub fn sample(&self, its: &Its) -> (Pdf<Color>, R) {
+        // shadow rays only need to leave the scene
+        let sray = R::r(its.p, -self.dir, 2. * self.bsphere.1);
+        (Pdf::sole(self.irradiance), sray)
+    }
+
+    // (emitted radiance, emitted ray, (pdf_pos, pdf_dir)) from a disk covering the scene
+    #[inline] pub fn sample_le(&self, s: F2) -> (Color, R, (F, F)) {
+        let (c, r) = self.bsphere;
+        let disk = conv!(F3::a2a(UniformDisk::warp(s), 0.) => V);
+        let o = c + (T::from_frame(self.dir.conv()) * disk - self.dir) * r;
+        (self.irradiance, R::unbounded(o, self.dir), ((F::PI * r.sq()).inv(), 1.))
+    }
+
+    #[inline] pub fn pdf_le(&self) -> (F, F) { ((F::PI * self.bsphere.1.sq()).inv(), 0.) }
+
+    #[inline] pub fn set_bsphere(&mut self, bsphere: (P, F)) { self.bsphere = bsphere; }
+
+    #[inline] pub fn power(&self, radius: F) -> F
+    { self.irradiance.luminance() * F::PI * radius.sq() }
+}
+
+
+#[derive(Debug, Deserialize)]
+struct DirectionalConfig {
+    irradiance:  Color,
+    direction:   F3,
+    #[serde(default)]
+    light_group: Option<String>,
+}
+
+impl From<DirectionalConfig> for Directional {
+    fn from(dc: DirectionalConfig) -> Self {
+        Self { irradiance: dc.irradiance, dir: conv!(dc.direction => V).unit(),
+               light_group: dc.light_group, bsphere: (P::ZERO, 0.) }
+    }
+}
@@ -1,30 +1,115 @@
 #[allow(clippy::wildcard_imports)]
 use graphite::*;
+use serde::Deserialize;
 
-use crate::color::Color;
+use crate::color::{self, Color};
 use crate::shape::{Intersectable, Shape, intersection::Its};
+use crate::texture::Tex;
 use crate::util::pdf::Pdf;
 
+// radiance emitted by a shape's surface
+#[derive(Debug, Deserialize)]
+#[serde(from="EmissionConfig")]
+pub struct Emission {
+        radiance:  Option<Tex<Color>>,
+        // strength and blackbody tint
+        scale:     Color,
+    pub two_sided: bool,
+}
+
+impl Emission {
+    #[inline] pub fn eval(&self, uv: F2) -> Color
+    { self.radiance.as_ref().map_or(Color::ONE, |r| r.eval(uv)) * self.scale }
+
+    #[inline] pub fn mean(&self) -> Color
+    { self.radiance.as_ref().map_or(Color::ONE, Tex::mean) * self.scale }
+}
+
 impl Shape {
     #[inline] pub fn eval(&self, uv: F2) -> Color
     { self.emission.as_ref().map_or(Color::ZERO, |e| e.eval(uv)) }
 
-    #[inline] pub fn sample<'a>(&'a self, its: &Its<'a>, s: F2) -> (Pdf<Color>, R) {
+    #[inline] pub fn emits_two_sided(&self) -> bool
+    { self.emission.as_ref().map_or(false, |e| e.two_sided) }
+
+    #[inline] pub fn sample<'a>(&'a self, its: &Its<'a>, s: F2) -> (Pdf<Color>, R, N) {
         if let Some(emission) = &self.emission {
-            let surface = self.sample_surface(s);
+            let (surface, p) = self.sample_towards(its.p, s);
             let sray = R::p2(its.p, surface.p);
-            let p = self.pdf(&surface, &sray);
-            let color = if p <= 0. { Color::ZERO }
+            let facing = emission.two_sided
+                         || F3::dot(surface.n.conv(), (-sray.d).conv()) > 0.;
+            let color = if p <= 0. || !facing { Color::ZERO }
                         else { emission.eval(surface.uv) / p };
-            (Pdf::new(color, p), sray)
+            (Pdf::new(color, p), sray, surface.n)
         } else { unreachable!() }
     }
 
-    #[inline] pub fn pdf(&self, its: &Its, sray: &R) -> F {
-        let ct = F3::dot(its.n.conv(), (-sray.d).conv());
-        if ct <= 0. { 0. } else { self.surface_pdf() * sray.t.sq() / ct }
+    // solid angle pdf of sampling its from the origin of sray
+    #[inline] pub fn pdf(&self, its: &Its, sray: &R) -> F { self.pdf_towards(sray.o, its) }
+
+    // (emitted radiance, emitted ray, surface normal, (pdf_pos, pdf_dir))
+    #[inline] pub fn sample_le(&self, s1: F2, mut s2: F2) -> (Color, R, N, (F, F)) {
+        let surface = self.sample_surface(s1);
+        let two_sided = self.emits_two_sided();
+        // two-sided emitters pick either side with equal probability
+        let back = two_sided && s2[0] >= 0.5;
+        if two_sided { s2[0] = 2. * s2[0] - if back { 1. } else { 0. }; }
+        let d = CosineHemisphere::warp(s2);
+        let pdf = CosineHemisphere::pdf(d) * if two_sided { 0.5 } else { 1. };
+        let d = T::from_frame(surface.n) * conv!(d => V);
+        (self.eval(surface.uv), R::unbounded(surface.p, if back { -d } else { d }), surface.n,
+         (self.surface_pdf(), pdf))
+    }
+
+    #[inline] pub fn pdf_le(&self, ray: &R, n: N) -> (F, F) {
+        let ct = F3::dot(n.conv(), ray.d.conv());
+        (self.surface_pdf(), if self.emits_two_sided() { F::abs(ct) * F::INV_PI * 0.5 }
+                             else if ct <= 0. { 0. } else { ct * F::INV_PI })
     }
 
-    #[inline] pub fn power(&self) -> F
-    { self.emission.as_ref().map_or(0., |e| (e.mean() * F::PI * self.surface_area()).luminance()) }
+    // two-sided emitters radiate from both sides of their surface
+    #[inline] pub fn power(&self) -> F {
+        self.emission.as_ref().map_or(0., |e| {
+            let sides = if e.two_sided { 2. } else { 1. };
+            (e.mean() * (sides * F::PI * self.surface_area())).luminance()
+        })
+    }
+}
+
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum EmissionConfig {
+    Options(EmissionOptions),
+    Radiance(Tex<Color>),
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct EmissionOptions {
+    #[serde(default)]
+    radiance:    Option<Tex<Color>>,
+    #[serde(default="EmissionOptions::default_strength")]
+    strength:    F,
+    // blackbody temperature in kelvin, tinting the radiance at unit luminance
+    #[serde(default)]
+    temperature: Option<F>,
+    #[serde(default)]
+    two_sided:   bool,
+}
+
+impl EmissionOptions { const fn default_strength() -> F { 1. } }
+
+impl From<EmissionConfig> for Emission {
+    fn from(ec: EmissionConfig) -> Self {
+        match ec {
+            EmissionConfig::Radiance(radiance) =>
+                Self { radiance: Some(radiance), scale: Color::ONE, two_sided: false },
+            EmissionConfig::Options(eo) => {
+                let tint = eo.temperature.map_or(Color::ONE, color::blackbody);
+                Self { radiance: eo.radiance, scale: tint * eo.strength,
+                       two_sided: eo.two_sided }
+            }
+        }
+    }
 }
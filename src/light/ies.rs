@@ -0,0 +1,132 @@
+use std::convert::TryFrom;
+use std::fs;
+
+#[allow(clippy::wildcard_imports)]
+use graphite::*;
+use serde::Deserialize;
+
+use crate::util::config;
+
+// resolution over (theta, phi) at which profiles are integrated
+const INTEGRATION_DIMS: I2 = A2(128, 256);
+
+// IESNA LM-63 photometric profile of type C, normalized to a peak of 1
+#[derive(Debug, Deserialize)]
+#[serde(try_from="String")]
+pub struct Profile {
+    // vertical angles from the nadir and horizontal angles, in degrees
+    vertical:   Box<[F]>,
+    horizontal: Box<[F]>,
+    // candela values, one row of vertical angles per horizontal angle
+    candela:    Box<[F]>,
+}
+
+impl Profile {
+    // relative intensity in local direction d, where +z is the nadir and +x the
+    // horizontal angle of 0 degrees
+    pub fn eval(&self, d: F3) -> F {
+        let len = F::sqrt(F3::dot(d, d));
+        if len <= 0. { return 0. }
+        let theta = F::acos(F::clamp(d[Z] / len, -1., 1.)).to_degrees();
+        let phi = F::atan2(d[Y], d[X]).to_degrees();
+        let phi = self.fold(if phi < 0. { phi + 360. } else { phi });
+
+        let (nv, nh) = (self.vertical.len(), self.horizontal.len());
+        let (v, tv) = match lookup(&self.vertical, theta) { Some(l) => l, None => return 0. };
+        let (h, th) = lookup(&self.horizontal, phi).unwrap_or((0, 0.));
+        let at = |h: usize, v: usize|
+            self.candela[usize::min(h, nh - 1) * nv + usize::min(v, nv - 1)];
+        let lerp = |a: F, b: F, t: F| t.mul_add(b - a, a);
+        lerp(lerp(at(h, v), at(h, v + 1), tv), lerp(at(h + 1, v), at(h + 1, v + 1), tv), th)
+    }
+
+    // integral over the sphere of the profile, weighted by w in the same local frame
+    pub fn integral(&self, w: impl Fn(F3) -> F) -> F {
+        let A2(nt, np) = INTEGRATION_DIMS;
+        let d_theta = F::PI / F::of(nt);
+        let d_phi = F::TWO_PI / F::of(np);
+        (0..nt).map(|i| {
+            let theta = (F::of(i) + 0.5) * d_theta;
+            let (st, ct) = (F::sin(theta), F::cos(theta));
+            (0..np).map(|j| {
+                let phi = (F::of(j) + 0.5) * d_phi;
+                let d = A3(st * F::cos(phi), st * F::sin(phi), ct);
+                self.eval(d) * w(d)
+            }).sum::<F>() * st * d_theta * d_phi
+        }).sum()
+    }
+
+    // map a horizontal angle in [0, 360) into the range covered by the data
+    #[inline] fn fold(&self, phi: F) -> F {
+        match self.horizontal.last() {
+            Some(&max) if max <= 90. => {
+                let phi = if phi > 180. { 360. - phi } else { phi };
+                if phi > 90. { 180. - phi } else { phi }
+            }
+            Some(&max) if max <= 180. => if phi > 180. { 360. - phi } else { phi },
+            _ => phi,
+        }
+    }
+}
+
+// (index of the interval containing x, interpolation weight) in ascending angles
+#[inline] fn lookup(angles: &[F], x: F) -> Option<(usize, F)> {
+    let (first, last) = (angles[0], angles[angles.len() - 1]);
+    if x < first || x > last { return None }
+    if angles.len() == 1 { return Some((0, 0.)) }
+    let i = angles.partition_point(|&a| a <= x).clamp(1, angles.len() - 1) - 1;
+    let span = angles[i + 1] - angles[i];
+    Some((i, if span > 0. { (x - angles[i]) / span } else { 0. }))
+}
+
+impl TryFrom<String> for Profile {
+    type Error = anyhow::Error;
+
+    fn try_from(src: String) -> anyhow::Result<Self> {
+        let text = fs::read_to_string(config::relative_path(src))?;
+        let mut lines = text.lines();
+        // keywords and labels precede the tilt specification
+        let tilt = lines.by_ref().map(str::trim).find(|l| l.starts_with("TILT="))
+                        .ok_or_else(|| anyhow::anyhow!("missing TILT line in IES profile"))?;
+        let mut values = lines.flat_map(|l| l.split(|c: char| c.is_whitespace() || c == ','))
+                              .filter(|v| !v.is_empty()).map(str::parse::<F>);
+        let mut next = || -> anyhow::Result<F> {
+            Ok(values.next().ok_or_else(|| anyhow::anyhow!("truncated IES profile"))??)
+        };
+
+        match &tilt[5..] {
+            "NONE" => (),
+            "INCLUDE" => {
+                next()?;
+                let n = usize::of(F::floori(next()?));
+                for _ in 0..2 * n { next()?; }
+            }
+            _ => anyhow::bail!("unsupported IES tilt file"),
+        }
+
+        // lamp count, lumens and multiplier are irrelevant once normalized
+        for _ in 0..3 { next()?; }
+        let nv = usize::of(F::floori(next()?));
+        let nh = usize::of(F::floori(next()?));
+        if F::floori(next()?) != 1 {
+            anyhow::bail!("unsupported IES photometric type (expected type C)");
+        }
+        // units, dimensions, ballast factors and input watts
+        for _ in 0..7 { next()?; }
+        if nv == 0 || nh == 0 { anyhow::bail!("empty IES profile"); }
+
+        let mut read = |n| (0..n).map(|_| next()).collect::<anyhow::Result<Vec<_>>>();
+        let vertical = read(nv)?;
+        let horizontal = read(nh)?;
+        let mut candela = read(nv * nh)?;
+        candela.iter_mut().for_each(|c| *c = F::max(*c, 0.));
+
+        let max = candela.iter().copied().fold(0., F::max);
+        if max <= 0. { anyhow::bail!("IES profile emits no light"); }
+        candela.iter_mut().for_each(|c| *c /= max);
+
+        Ok(Self { vertical: vertical.into_boxed_slice(),
+                  horizontal: horizontal.into_boxed_slice(),
+                  candela: candela.into_boxed_slice() })
+    }
+}
@@ -3,32 +3,125 @@ use graphite::*;
 use serde::Deserialize;
 
 use crate::color::Color;
+use crate::image::rect::Rect;
 use crate::shape::intersection::Its;
 use crate::texture::Tex;
-use crate::util::pdf::Pdf;
+use crate::util::{dpdf::DiscretePdf2D, pdf::Pdf};
+
+// resolution of the sampling distribution for textures that are not bitmaps
+const FALLBACK_DIMS: I2 = A2(64, 1);
 
 #[derive(Debug, Deserialize)]
+#[serde(from="InfiniteConfig")]
 pub struct Infinite {
-    intensity: Tex<Color>,
+        intensity:   Tex<Color>,
+    pub light_group: Option<String>,
+        dist:        EnvDist,
+}
+
+// distribution over the sphere of directions, piecewise-constant in (theta, phi)
+#[derive(Debug)]
+pub struct EnvDist {
+    dist:       DiscretePdf2D,
+    // luminance integrated over the sphere of directions
+    l_integral: F,
 }
 
 impl Infinite {
-    #[inline] pub fn sample(&self, its: &Its, s: F2) -> (Pdf<Color>, R) {
-        let theta_phi = s * A2(F::PI, F::TWO_PI);
-        let sray = R::unbounded(its.p, Frame::spher2cart(theta_phi).conv());
-        (Pdf::new(self.intensity.eval(s), Self::pdf(its, &sray)), sray)
+    #[inline] pub fn sample(&self, its: &Its, s: F2) -> (Pdf<Color>, R)
+    { self.dist.sample(its, s, |ray| self.eval_env(ray)) }
+
+    // solid angle pdf of sampling direction d
+    #[inline] pub fn pdf(&self, d: V) -> F { self.dist.pdf(d) }
+
+    // (emitted radiance, emitted ray, (pdf_pos, pdf_dir)) for a scene bounding sphere
+    #[inline] pub fn sample_le(&self, bsphere: (P, F), s1: F2, s2: F2) -> (Color, R, (F, F))
+    { self.dist.sample_le(bsphere, s1, s2, |ray| self.eval_env(ray)) }
+
+    #[inline] pub fn pdf_le(&self, bsphere: (P, F), ray: &R) -> (F, F)
+    { self.dist.pdf_le(bsphere, ray) }
+
+    #[inline] pub fn eval_env(&self, ray: &R) -> Color
+    { self.intensity.eval(EnvDist::uv(ray.d)) }
+
+    #[inline] pub fn power(&self, radius: F) -> F { self.dist.power(radius) }
+}
+
+impl EnvDist {
+    // from the luminance of a dims grid of cells over (theta, phi)
+    pub fn new(dims: I2, lum: impl Fn(I2) -> F) -> Self {
+        // u spans theta, so cells are weighted by the solid angle they subtend
+        let w = |p: I2| lum(p) * F::sin(F::PI * (F::of(p[X]) + 0.5) / F::of(dims[X]));
+        let sum = Rect::at_origin(dims).positions().map(w).sum::<F>();
+        Self { dist: DiscretePdf2D::new(dims, w),
+               l_integral: sum * F::PI * F::TWO_PI / F::of(dims.product()) }
     }
 
-    #[inline] pub fn pdf(its: &Its, sray: &R) -> F
-    { F::INV_2PI * F::INV_PI / F::sqrt(1. - F3::dot(its.n.conv(), sray.d.conv()).sq()) }
+    // sinθ-only weighting with a known luminance integral
+    pub fn uniform(l_integral: F) -> Self
+    { Self { l_integral, ..Self::new(FALLBACK_DIMS, |_| 1.) } }
 
-    #[inline] pub fn eval_env(&self, ray: &R) -> Color {
-        let uv = Frame::cart2spher(conv!(ray.d => F3).swizzle(0, 2, 1));
-        self.intensity.eval(uv * A2(F::INV_PI, F::INV_2PI))
+    #[inline] pub fn sample(&self, its: &Its, s: F2, eval: impl Fn(&R) -> Color)
+        -> (Pdf<Color>, R)
+    {
+        let (uv, p) = self.sample_dir(s);
+        let sray = R::unbounded(its.p, Self::dir(uv));
+        let color = if p <= 0. { Color::ZERO } else { eval(&sray) / p };
+        (Pdf::new(color, p), sray)
     }
 
-    #[inline] pub fn power(&self) -> F {
-        // TODO how to incorporate scene bsphere surface area?
-        self.intensity.mean().luminance() * F::PI * 100.
+    #[inline] pub fn pdf(&self, d: V) -> F {
+        let uv = Self::uv(d);
+        let st = F::sin(uv[X] * F::PI);
+        if st <= 0. { 0. } else { self.dist.pdf(uv) * F::INV_2PI * F::INV_PI / st }
+    }
+
+    // (uv, solid angle pdf)
+    #[inline] fn sample_dir(&self, s: F2) -> (F2, F) {
+        let (uv, p) = self.dist.sample(s);
+        let st = F::sin(uv[X] * F::PI);
+        (uv, if st <= 0. { 0. } else { p * F::INV_2PI * F::INV_PI / st })
+    }
+
+    #[inline] pub fn sample_le(&self, (c, r): (P, F), s1: F2, s2: F2,
+                               eval: impl Fn(&R) -> Color) -> (Color, R, (F, F)) {
+        let (uv, p) = self.sample_dir(s1);
+        let d = Self::dir(uv);
+        let disk = conv!(F3::a2a(UniformDisk::warp(s2), 0.) => V);
+        let o = c + (T::from_frame(d.conv()) * disk + d) * r;
+        let ray = R::unbounded(o, -d);
+        (eval(&R::unbounded(o, d)), ray, ((F::PI * r.sq()).inv(), p))
+    }
+
+    #[inline] pub fn pdf_le(&self, (_, r): (P, F), ray: &R) -> (F, F)
+    { ((F::PI * r.sq()).inv(), self.pdf(-ray.d)) }
+
+    #[inline] pub fn uv(d: V) -> F2 {
+        let A2(u, v) = Frame::cart2spher(conv!(d => F3).swizzle(0, 2, 1))
+                     * A2(F::INV_PI, F::INV_2PI);
+        A2(u, v - F::floor(v))
+    }
+
+    #[inline] pub fn dir(uv: F2) -> V
+    { conv!(Frame::spher2cart(uv * A2(F::PI, F::TWO_PI)).swizzle(0, 2, 1) => V) }
+
+    // power through a disk of the scene's bounding radius, from every direction
+    #[inline] pub fn power(&self, radius: F) -> F { self.l_integral * F::PI * radius.sq() }
+}
+
+#[derive(Debug, Deserialize)]
+struct InfiniteConfig {
+    intensity:   Tex<Color>,
+    #[serde(default)]
+    light_group: Option<String>,
+}
+
+impl From<InfiniteConfig> for Infinite {
+    fn from(ic: InfiniteConfig) -> Self {
+        let dist = match &ic.intensity {
+            Tex::Bitmap(b) => EnvDist::new(b.rect.dims, |p| b[p].luminance()),
+            t => EnvDist::uniform(t.mean().luminance() * F::FOUR_PI),
+        };
+        Self { intensity: ic.intensity, light_group: ic.light_group, dist }
     }
 }
@@ -1,6 +1,12 @@
+mod bvh;
+mod directional;
 mod emitter;
+mod ies;
 mod infinite;
 mod point;
+pub mod selection;
+mod spot;
+mod sunsky;
 
 use std::sync::Arc;
 
@@ -12,45 +18,136 @@ use crate::color::Color;
 use crate::shape::{Shape, intersection::Its};
 use crate::util::pdf::Pdf;
 
+pub use emitter::Emission;
+
+use directional::Directional;
 use infinite::Infinite;
 use point::Point;
+use spot::Spot;
+use sunsky::{Sky, Sun};
 
 #[derive(Debug, Deserialize)]
 #[serde(tag="type")]
 pub enum Light {
     #[serde(skip)] Area(Arc<Shape>),
+    #[serde(rename="directional", alias="distant")]
+    Directional(Directional),
     #[serde(rename="infinitelight")]
     Infinite(Infinite),
     #[serde(rename="pointlight")]
     Point(Point),
+    #[serde(rename="sky")]
+    Sky(Sky),
+    #[serde(rename="spotlight")]
+    Spot(Spot),
+    #[serde(rename="sun")]
+    Sun(Sun),
 }
 
 impl Light {
-    #[inline] pub fn sample<'a>(&'a self, its: &Its<'a>, s: F2) -> (Pdf<Color>, R) {
+    // (radiance / pdf + solid angle pdf, shadow ray, normal at the sampled point)
+    #[inline] pub fn sample<'a>(&'a self, its: &Its<'a>, s: F2) -> (Pdf<Color>, R, N) {
         match self {
             Self::Area(l) => l.sample(its, s),
-            Self::Infinite(l) => l.sample(its, s),
-            Self::Point(l) => l.sample(its),
+            Self::Directional(l) => { let (l, sray) = l.sample(its); (l, sray, N::ZERO) }
+            Self::Infinite(l) => { let (l, sray) = l.sample(its, s); (l, sray, N::ZERO) }
+            Self::Point(l) => { let (l, sray) = l.sample(its); (l, sray, N::ZERO) }
+            Self::Sky(l) => { let (l, sray) = l.sample(its, s); (l, sray, N::ZERO) }
+            Self::Spot(l) => { let (l, sray) = l.sample(its); (l, sray, N::ZERO) }
+            Self::Sun(l) => { let (l, sray) = l.sample(its, s); (l, sray, N::ZERO) }
+        }
+    }
+
+    // (emitted radiance, emitted ray, normal at origin, (pdf_pos, pdf_dir))
+    #[inline]
+    pub fn sample_le(&self, bsphere: (P, F), s1: F2, s2: F2) -> (Color, R, N, (F, F)) {
+        match self {
+            Self::Area(l) => l.sample_le(s1, s2),
+            Self::Directional(l) => { let (l, ray, p) = l.sample_le(s1);
+                                      (l, ray, N::ZERO, p) }
+            Self::Infinite(l) => { let (l, ray, p) = l.sample_le(bsphere, s1, s2);
+                                   (l, ray, N::ZERO, p) }
+            Self::Point(l) => { let (l, ray, p) = l.sample_le(s2); (l, ray, N::ZERO, p) }
+            Self::Sky(l) => { let (l, ray, p) = l.sample_le(bsphere, s1, s2);
+                              (l, ray, N::ZERO, p) }
+            Self::Spot(l) => { let (l, ray, p) = l.sample_le(s2); (l, ray, N::ZERO, p) }
+            Self::Sun(l) => { let (l, ray, p) = l.sample_le(bsphere, s1, s2);
+                              (l, ray, N::ZERO, p) }
+        }
+    }
+
+    #[inline] pub fn pdf_le(&self, bsphere: (P, F), ray: &R, n: N) -> (F, F) {
+        match self {
+            Self::Area(l) => l.pdf_le(ray, n),
+            Self::Directional(l) => l.pdf_le(),
+            Self::Infinite(l) => l.pdf_le(bsphere, ray),
+            Self::Point(_) => (0., F::INV_4PI),
+            Self::Sky(l) => l.pdf_le(bsphere, ray),
+            Self::Spot(l) => l.pdf_le(ray),
+            Self::Sun(l) => l.pdf_le(bsphere, ray),
+        }
+    }
+
+    // solid angle pdf of sampling direction d towards an environment light
+    #[inline] pub fn pdf_env(&self, d: V) -> F {
+        match self {
+            Self::Infinite(l) => l.pdf(d),
+            Self::Sky(l) => l.pdf(d),
+            Self::Sun(l) => l.pdf(d),
+            _ => 0.,
         }
     }
 
     #[inline] pub const fn is_env_light(&self) -> bool
-    { matches!(self, Self::Infinite(_)) }
+    { matches!(self, Self::Infinite(_) | Self::Sky(_) | Self::Sun(_)) }
+
+    // lights at infinity, whose emitted rays are positioned on a disk facing the scene
+    #[inline] pub const fn is_infinite(&self) -> bool
+    { self.is_env_light() || matches!(self, Self::Directional(_)) }
+
+    #[inline] pub const fn is_delta(&self) -> bool
+    { matches!(self, Self::Directional(_) | Self::Point(_) | Self::Spot(_)) }
+
+    #[inline] pub const fn is_area(&self) -> bool { matches!(self, Self::Area(_)) }
+
+    #[inline] pub fn group(&self) -> Option<&str> {
+        match self {
+            Self::Area(s) => s.light_group.as_deref(),
+            Self::Directional(l) => l.light_group.as_deref(),
+            Self::Infinite(l) => l.light_group.as_deref(),
+            Self::Point(l) => l.light_group.as_deref(),
+            Self::Sky(l) => l.light_group.as_deref(),
+            Self::Spot(l) => l.light_group.as_deref(),
+            Self::Sun(l) => l.light_group.as_deref(),
+        }
+    }
 
     #[inline] pub fn eval_env(&self, ray: &R) -> Color {
         match self {
             Self::Infinite(l) => l.eval_env(ray),
+            Self::Sky(l) => l.eval_env(ray),
+            Self::Sun(l) => l.eval_env(ray),
             _ => Color::ZERO,
         }
     }
 
-    #[inline] pub fn power(&self) -> F {
+    // bsphere bounds the scene, which is needed to estimate the power of infinite lights
+    #[inline] pub fn power(&self, bsphere: (P, F)) -> F {
         match self {
             Self::Area(l) => l.power(),
-            Self::Infinite(l) => l.power(),
+            Self::Directional(l) => l.power(bsphere.1),
+            Self::Infinite(l) => l.power(bsphere.1),
             Self::Point(l) => l.power(),
+            Self::Sky(l) => l.power(bsphere.1),
+            Self::Spot(l) => l.power(),
+            Self::Sun(l) => l.power(bsphere.1),
         }
     }
+
+    // lights that illuminate the whole scene need its bounds
+    #[inline] pub fn preprocess(&mut self, bsphere: (P, F)) {
+        if let Self::Directional(l) = self { l.set_bsphere(bsphere); }
+    }
 }
 
 impl From<Arc<Shape>> for Light
@@ -61,3 +158,15 @@ impl From<Infinite> for Light
 
 impl From<Point> for Light
 { #[inline] fn from(l: Point) -> Self { Self::Point(l) } }
+
+impl From<Directional> for Light
+{ #[inline] fn from(l: Directional) -> Self { Self::Directional(l) } }
+
+impl From<Spot> for Light
+{ #[inline] fn from(l: Spot) -> Self { Self::Spot(l) } }
+
+impl From<Sky> for Light
+{ #[inline] fn from(l: Sky) -> Self { Self::Sky(l) } }
+
+impl From<Sun> for Light
+{ #[inline] fn from(l: Sun) -> Self { Self::Sun(l) } }
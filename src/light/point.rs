@@ -6,22 +6,60 @@ use crate::color::Color;
 use crate::shape::intersection::Its;
 use crate::util::pdf::Pdf;
 
+use super::ies::Profile;
+
 #[derive(Debug, Deserialize)]
+#[serde(from="PointConfig")]
 pub struct Point {
-    #[serde(rename="power", deserialize_with="de_intensity")]
-    intensity: Color,
-    position:  P,
+        intensity:   Color,
+        position:    P,
+        profile:     Option<Profile>,
+        // integral of the relative intensity over the sphere of directions
+        solid_angle: F,
+    pub light_group: Option<String>,
 }
 
 impl Point {
     #[inline] pub fn sample(&self, its: &Its) -> (Pdf<Color>, R) {
         let sray = R::p2(its.p, self.position);
-        (Pdf::sole(self.intensity / sray.t.sq()), sray)
+        (Pdf::sole(self.eval(-sray.d) / sray.t.sq()), sray)
+    }
+
+    // (emitted intensity, emitted ray, (pdf_pos, pdf_dir))
+    #[inline] pub fn sample_le(&self, s: F2) -> (Color, R, (F, F)) {
+        let ray = R::unbounded(self.position, UniformSphere::warp(s).conv());
+        (self.eval(ray.d), ray, (1., F::INV_4PI))
     }
 
-    #[inline] pub fn power(&self) -> F { self.intensity.luminance() * F::FOUR_PI }
+    // intensity emitted in direction d
+    #[inline] fn eval(&self, d: V) -> Color {
+        self.profile.as_ref().map_or(self.intensity, |profile| {
+            // profiles point down the y axis
+            let d = conv!(d => F3);
+            self.intensity * profile.eval(A3(d[X], d[Z], -d[Y]))
+        })
+    }
+
+    #[inline] pub const fn position(&self) -> P { self.position }
+
+    #[inline] pub fn power(&self) -> F { self.intensity.luminance() * self.solid_angle }
 }
 
-fn de_intensity<'de, D>(de: D) -> Result<Color, D::Error>
-where D: serde::Deserializer<'de>
-{ Color::deserialize(de).map(|power| power * F::INV_4PI) }
+
+#[derive(Debug, Deserialize)]
+struct PointConfig {
+    power:       Color,
+    position:    P,
+    #[serde(default)]
+    profile:     Option<Profile>,
+    #[serde(default)]
+    light_group: Option<String>,
+}
+
+impl From<PointConfig> for Point {
+    fn from(pc: PointConfig) -> Self {
+        let solid_angle = pc.profile.as_ref().map_or(F::FOUR_PI, |p| p.integral(|_| 1.));
+        Self { intensity: pc.power / solid_angle, position: pc.position, profile: pc.profile,
+               solid_angle, light_group: pc.light_group }
+    }
+}
@@ -0,0 +1,108 @@
+use std::sync::Arc;
+
+#[allow(clippy::wildcard_imports)]
+use graphite::*;
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use serde::Deserialize;
+
+use crate::sampler::Sampler;
+use crate::shape::intersection::Its;
+use crate::util::dpdf::DiscretePdf;
+
+use super::{Light, bvh::LightBvh};
+
+const SPATIAL_RES: I = 8;
+const SPATIAL_SAMPLES: I = 16;
+// fraction of a cell's mean contribution every light is guaranteed
+const SPATIAL_MIN_WEIGHT: F = 1e-3;
+
+// how lights are chosen for next-event estimation
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(rename_all="snake_case")]
+pub enum Strategy {
+    Uniform,
+    Power,
+    Spatial,
+    Bvh,
+}
+
+impl Default for Strategy { fn default() -> Self { Self::Power } }
+
+#[derive(Debug)]
+pub enum Selection {
+    Global(DiscretePdf),
+    Spatial(Spatial),
+    Bvh(LightBvh),
+}
+
+// per-voxel distributions over the lights, weighted by their unoccluded
+// contribution to points within the voxel
+#[derive(Debug)]
+pub struct Spatial {
+    lo:    F3,
+    size:  F3,
+    cells: Box<[DiscretePdf]>,
+}
+
+impl Selection {
+    pub fn new(strategy: Strategy, lights: &[Arc<Light>], bbox: (F3, F3), bsphere: (P, F))
+        -> Self
+    {
+        match strategy {
+            Strategy::Uniform => Self::Global(DiscretePdf::new(lights, |_| 1.)),
+            Strategy::Power =>
+                Self::Global(DiscretePdf::new(lights, |light| light.power(bsphere))),
+            Strategy::Spatial => Self::Spatial(Spatial::new(lights, bbox)),
+            Strategy::Bvh => Self::Bvh(LightBvh::new(lights, bsphere)),
+        }
+    }
+
+    // (light index, selection probability) for lighting its
+    #[inline] pub fn sample(&self, its: &Its, s: &mut F) -> Option<(usize, F)> {
+        match self {
+            Self::Global(dpdf) => Some(dpdf.sample(s)),
+            Self::Spatial(spatial) => Some(spatial.cell(its.p).sample(s)),
+            Self::Bvh(bvh) => bvh.sample(its, s),
+        }
+    }
+
+    // probability of sample choosing the given light for lighting its
+    #[inline] pub fn pmf(&self, its: &Its, light: usize) -> F {
+        match self {
+            Self::Global(dpdf) => dpdf.prob(light),
+            Self::Spatial(spatial) => spatial.cell(its.p).prob(light),
+            Self::Bvh(bvh) => bvh.pmf(its, light),
+        }
+    }
+}
+
+impl Spatial {
+    fn new(lights: &[Arc<Light>], (lo, hi): (F3, F3)) -> Self {
+        let size = ((hi - lo) / F::of(SPATIAL_RES)).map(|s| F::max(s, F::EPS));
+        let cells = (0..SPATIAL_RES.pow(3)).into_par_iter().map(|idx| {
+            let cell = A3(idx % SPATIAL_RES, idx / SPATIAL_RES % SPATIAL_RES,
+                          idx / SPATIAL_RES / SPATIAL_RES).map(F::of);
+            let mut sampler = Sampler::for_stream(0, idx);
+            let mut contrib = vec![0.; lights.len()];
+            for _ in 0..SPATIAL_SAMPLES {
+                let p = lo + (cell + A3(sampler.rng(), sampler.rng(), sampler.rng())) * size;
+                let its = Its::new(conv!(p => P), N::ZERO, F2::ZERO, 0.);
+                for (c, light) in contrib.iter_mut().zip(lights) {
+                    let (l, _, _) = light.sample(&its, sampler.next_2d());
+                    if l.pdf > 0. { *c += l.val.luminance(); }
+                }
+            }
+            let mean = contrib.iter().sum::<F>() / F::of(I::of(lights.len()));
+            if mean > 0. {
+                DiscretePdf::new(&contrib, |&c| F::max(c, SPATIAL_MIN_WEIGHT * mean))
+            } else { DiscretePdf::new(&contrib, |_| 1.) }
+        }).collect::<Vec<_>>().into_boxed_slice();
+        Self { lo, size, cells }
+    }
+
+    #[inline] fn cell(&self, p: P) -> &DiscretePdf {
+        let A3(x, y, z) = ((conv!(p => F3) - self.lo) / self.size)
+                              .map(|c| I::clamp(F::floori(c), 0, SPATIAL_RES - 1));
+        &self.cells[usize::of((z * SPATIAL_RES + y) * SPATIAL_RES + x)]
+    }
+}
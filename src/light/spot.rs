@@ -0,0 +1,108 @@
+#[allow(clippy::wildcard_imports)]
+use graphite::*;
+use serde::Deserialize;
+
+use crate::color::Color;
+use crate::shape::intersection::Its;
+use crate::util::pdf::Pdf;
+
+use super::ies::Profile;
+
+#[derive(Debug, Deserialize)]
+#[serde(from="SpotConfig")]
+pub struct Spot {
+        intensity:   Color,
+        position:    P,
+        frame:       T,
+        profile:     Option<Profile>,
+    pub light_group: Option<String>,
+        // cosines of the angles at which the falloff begins and the cone ends
+        cos_falloff: F,
+        cos_cutoff:  F,
+        // integral of the relative intensity over the sphere of directions
+        solid_angle: F,
+}
+
+impl Spot {
+    #[inline] pub fn sample(&self, its: &Its) -> (Pdf<Color>, R) {
+        let sray = R::p2(its.p, self.position);
+        (Pdf::sole(self.eval(-sray.d) / sray.t.sq()), sray)
+    }
+
+    // (emitted intensity, emitted ray, (pdf_pos, pdf_dir))
+    #[inline] pub fn sample_le(&self, s: F2) -> (Color, R, (F, F)) {
+        let ct = s[0].mul_add(self.cos_cutoff - 1., 1.);
+        let st = F::sqrt(F::max(0., 1. - ct.sq()));
+        let phi = s[1] * F::TWO_PI;
+        let d = self.frame * conv!(A3(st * F::cos(phi), st * F::sin(phi), ct) => V);
+        (self.eval(d), R::unbounded(self.position, d), (1., self.pdf_cone()))
+    }
+
+    #[inline] pub fn pdf_le(&self, ray: &R) -> (F, F) {
+        let ct = Frame::ct(self.frame / ray.d);
+        (0., if ct < self.cos_cutoff { 0. } else { self.pdf_cone() })
+    }
+
+    // intensity emitted in direction d
+    #[inline] fn eval(&self, d: V) -> Color {
+        let d = self.frame / d;
+        let profile = self.profile.as_ref().map_or(1., |profile| profile.eval(d.conv()));
+        self.intensity * (self.falloff(Frame::ct(d)) * profile)
+    }
+
+    #[inline] fn falloff(&self, ct: F) -> F {
+        if ct < self.cos_cutoff { 0. }
+        else if ct >= self.cos_falloff { 1. }
+        else {
+            let t = (ct - self.cos_cutoff) / (self.cos_falloff - self.cos_cutoff);
+            t.sq() * t.mul_add(-2., 3.)
+        }
+    }
+
+    #[inline] fn pdf_cone(&self) -> F { (F::TWO_PI * (1. - self.cos_cutoff)).inv() }
+
+    #[inline] pub const fn position(&self) -> P { self.position }
+
+    #[inline] pub fn direction(&self) -> V { self.frame * conv!(A3(0., 0., 1.) => V) }
+
+    #[inline] pub const fn cone(&self) -> (F, F) { (self.cos_falloff, self.cos_cutoff) }
+
+    #[inline] pub fn power(&self) -> F { self.intensity.luminance() * self.solid_angle }
+}
+
+
+#[derive(Debug, Deserialize)]
+struct SpotConfig {
+    power:       Color,
+    position:    P,
+    direction:   F3,
+    // half-angle of the cone in degrees
+    cutoff:      F,
+    // half-angle in degrees beyond which the intensity starts to fall off
+    #[serde(default)]
+    falloff:     Option<F>,
+    #[serde(default)]
+    profile:     Option<Profile>,
+    #[serde(default)]
+    light_group: Option<String>,
+}
+
+impl From<SpotConfig> for Spot {
+    fn from(sc: SpotConfig) -> Self {
+        let cos_cutoff = F::cos(sc.cutoff.to_radians());
+        let falloff = sc.falloff.map_or(0.75 * sc.cutoff, |f| F::min(f, sc.cutoff));
+        let cos_falloff = F::cos(falloff.to_radians());
+        let frame = T::from_frame(conv!(sc.direction => V).unit().conv());
+        let mut spot = Self { intensity: sc.power, position: sc.position, frame,
+                              profile: sc.profile, light_group: sc.light_group,
+                              cos_falloff, cos_cutoff, solid_angle: 1. };
+        let solid_angle = spot.profile.as_ref().map_or_else(
+            // the falloff approximated linearly in cos
+            || F::TWO_PI * (1. - 0.5 * (cos_falloff + cos_cutoff)),
+            |profile| profile.integral(|d| spot.falloff(d[Z])));
+        // spread the configured power over the cone
+        spot.intensity = sc.power / solid_angle;
+        spot.solid_angle = solid_angle;
+        spot
+    }
+}
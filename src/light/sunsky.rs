@@ -0,0 +1,230 @@
+#[allow(clippy::wildcard_imports)]
+use graphite::*;
+use serde::Deserialize;
+
+use crate::color::{Color, Rgb};
+use crate::image::rect::Rect;
+use crate::shape::intersection::Its;
+use crate::util::pdf::Pdf;
+
+use super::infinite::EnvDist;
+
+// resolution of the tabulated sky used for importance sampling
+const SKY_DIMS: I2 = A2(64, 128);
+// luminance of the sun outside the atmosphere, in the sky's units of kcd/m^2
+const SUN_LUMINANCE: F = 2e6;
+// wavelengths in micrometres standing in for the r, g and b channels
+const RGB_WAVELENGTHS: F3 = A3(0.610, 0.550, 0.465);
+
+// analytic clear sky model of Preetham et al. 1999, with y as the zenith
+#[derive(Debug, Deserialize)]
+#[serde(from="SunSkyConfig")]
+pub struct Sky {
+        sun:         F3,
+        // perez coefficients for x, y and luminance
+        perez:       [[F; 5]; 3],
+        // zenith values of x, y and luminance, divided by the perez term at the zenith
+        zenith:      F3,
+        // radiance reflected by the ground below the horizon
+        ground:      Color,
+        scale:       F,
+    pub light_group: Option<String>,
+        dist:        EnvDist,
+}
+
+// disk of the sun as seen through the atmosphere of the matching sky
+#[derive(Debug, Deserialize)]
+#[serde(from="SunSkyConfig")]
+pub struct Sun {
+        sun:         F3,
+        cos_max:     F,
+        radiance:    Color,
+    pub light_group: Option<String>,
+}
+
+impl Sky {
+    #[inline] pub fn sample(&self, its: &Its, s: F2) -> (Pdf<Color>, R)
+    { self.dist.sample(its, s, |ray| self.eval_env(ray)) }
+
+    #[inline] pub fn pdf(&self, d: V) -> F { self.dist.pdf(d) }
+
+    #[inline] pub fn sample_le(&self, bsphere: (P, F), s1: F2, s2: F2) -> (Color, R, (F, F))
+    { self.dist.sample_le(bsphere, s1, s2, |ray| self.eval_env(ray)) }
+
+    #[inline] pub fn pdf_le(&self, bsphere: (P, F), ray: &R) -> (F, F)
+    { self.dist.pdf_le(bsphere, ray) }
+
+    #[inline] pub fn eval_env(&self, ray: &R) -> Color
+    { let d = conv!(ray.d => F3); self.radiance(d / F::sqrt(F3::dot(d, d))) }
+
+    #[inline] pub fn power(&self, radius: F) -> F { self.dist.power(radius) }
+
+    #[inline] fn radiance(&self, d: F3) -> Color {
+        if d[Y] <= 0. { return self.ground }
+        self.sky(d)
+    }
+
+    #[inline] fn sky(&self, d: F3) -> Color {
+        let cos_g = F::clamp(F3::dot(d, self.sun), -1., 1.);
+        let A3(x, y, lum) = A3(self.zenith[0] * perez(&self.perez[0], d[Y], cos_g),
+                               self.zenith[1] * perez(&self.perez[1], d[Y], cos_g),
+                               self.zenith[2] * perez(&self.perez[2], d[Y], cos_g));
+        if y <= 0. { return Color::ZERO }
+        let xyz = A3(x / y * lum, lum, (1. - x - y) / y * lum);
+        Rgb(Color::from_xyz(xyz * self.scale).0.map(|c| F::max(c, 0.)))
+    }
+}
+
+impl Sun {
+    #[inline] pub fn sample(&self, its: &Its, s: F2) -> (Pdf<Color>, R) {
+        let (d, p) = self.sample_dir(s);
+        (Pdf::new(if p > 0. { self.radiance / p } else { Color::ZERO }, p),
+         R::unbounded(its.p, d))
+    }
+
+    #[inline] pub fn pdf(&self, d: V) -> F
+    { if self.in_disk(d) { (F::TWO_PI * (1. - self.cos_max)).inv() } else { 0. } }
+
+    #[inline] pub fn sample_le(&self, (c, r): (P, F), s1: F2, s2: F2) -> (Color, R, (F, F)) {
+        let (d, p) = self.sample_dir(s1);
+        let disk = conv!(F3::a2a(UniformDisk::warp(s2), 0.) => V);
+        let o = c + (T::from_frame(d.conv()) * disk + d) * r;
+        (self.radiance, R::unbounded(o, -d), ((F::PI * r.sq()).inv(), p))
+    }
+
+    #[inline] pub fn pdf_le(&self, (_, r): (P, F), ray: &R) -> (F, F)
+    { ((F::PI * r.sq()).inv(), self.pdf(-ray.d)) }
+
+    #[inline] pub fn eval_env(&self, ray: &R) -> Color
+    { if self.in_disk(ray.d) { self.radiance } else { Color::ZERO } }
+
+    // power through a disk of the scene's bounding radius
+    #[inline] pub fn power(&self, radius: F) -> F {
+        self.radiance.luminance() * F::TWO_PI * (1. - self.cos_max) * F::PI * radius.sq()
+    }
+
+    // uniform direction within the cone subtended by the sun
+    #[inline] fn sample_dir(&self, s: F2) -> (V, F) {
+        let ct = s[0].mul_add(self.cos_max - 1., 1.);
+        let st = F::sqrt(F::max(0., 1. - ct.sq()));
+        let phi = s[1] * F::TWO_PI;
+        let local = conv!(A3(st * F::cos(phi), st * F::sin(phi), ct) => V);
+        (T::from_frame(conv!(self.sun => V).conv()) * local,
+         (F::TWO_PI * (1. - self.cos_max)).inv())
+    }
+
+    #[inline] fn in_disk(&self, d: V) -> bool {
+        let d = conv!(d => F3);
+        F3::dot(d, self.sun) >= self.cos_max * F::sqrt(F3::dot(d, d))
+    }
+}
+
+// (1 + a e^(b / cos θ)) (1 + c e^(d γ) + e cos^2 γ)
+#[inline] fn perez(c: &[F; 5], cos_t: F, cos_g: F) -> F {
+    let g = F::acos(cos_g);
+    (1. + c[0] * F::exp(c[1] / F::max(cos_t, F::EPS)))
+        * (1. + c[2] * F::exp(c[3] * g) + c[4] * cos_g.sq())
+}
+
+// per-channel transmittance of sunlight through rayleigh and aerosol scattering
+fn sun_transmittance(cos_t: F, turbidity: F) -> F3 {
+    let theta = F::acos(cos_t).to_degrees();
+    if theta >= 90. { return F3::ZERO }
+    let m = (cos_t + 0.15 * F::powf(93.885 - theta, -1.253)).inv();
+    let beta = turbidity.mul_add(0.046_08, -0.045_86);
+    RGB_WAVELENGTHS.map(|l| F::exp(-0.008_735 * F::powf(l, -4.08) * m)
+                            * F::exp(-beta * F::powf(l, -1.3) * m))
+}
+
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+struct SunSkyConfig {
+    // direction towards the sun
+    sun_direction: F3,
+    turbidity:     F,
+    ground_albedo: Color,
+    // conversion from kcd/m^2 to scene radiance
+    scale:         F,
+    // angular radius of the sun in degrees
+    sun_radius:    F,
+    light_group:   Option<String>,
+}
+
+impl Default for SunSkyConfig {
+    fn default() -> Self {
+        Self { sun_direction: A3(0., 1., 0.), turbidity: 3.,
+               ground_albedo: Rgb(F3::rep(0.3)), scale: 0.1, sun_radius: 0.2667, light_group: None }
+    }
+}
+
+impl SunSkyConfig {
+    #[inline] fn sun(&self) -> F3 {
+        let d = self.sun_direction;
+        d / F::sqrt(F3::dot(d, d))
+    }
+}
+
+impl From<SunSkyConfig> for Sky {
+    fn from(sc: SunSkyConfig) -> Self {
+        let sun = sc.sun();
+        let t = sc.turbidity;
+        let theta_s = F::acos(F::clamp(sun[Y], -1., 1.));
+        let ts = A3(theta_s.powi(3), theta_s.sq(), theta_s);
+
+        let chi = (4. / 9. - t / 120.) * theta_s.mul_add(-2., F::PI);
+        let zenith_lum = t.mul_add(4.0453, -4.9710)
+                          .mul_add(F::tan(chi), t.mul_add(-0.2155, 2.4192));
+        let chromaticity = |c2: [F; 4], c1: [F; 4], c0: [F; 4]| {
+            let poly = |c: [F; 4]| F3::dot(A3(c[0], c[1], c[2]), ts) + c[3];
+            t.sq().mul_add(poly(c2), t.mul_add(poly(c1), poly(c0)))
+        };
+        let zenith_x = chromaticity([0.00166, -0.00375, 0.00209, 0.],
+                                    [-0.02903, 0.06377, -0.03202, 0.00394],
+                                    [0.11693, -0.21196, 0.06052, 0.25886]);
+        let zenith_y = chromaticity([0.00275, -0.00610, 0.00317, 0.],
+                                    [-0.04214, 0.08970, -0.04153, 0.00516],
+                                    [0.15346, -0.26756, 0.06670, 0.26688]);
+
+        let coeffs = |c: [[F; 2]; 5]| c.map(|[a, b]| a.mul_add(t, b));
+        let perez_c = [
+            coeffs([[-0.0193, -0.2592], [-0.0665, 0.0008], [-0.0004, 0.2125],
+                    [-0.0641, -0.8989], [-0.0033, 0.0452]]),
+            coeffs([[-0.0167, -0.2608], [-0.0950, 0.0092], [-0.0079, 0.2102],
+                    [-0.0441, -1.6537], [-0.0109, 0.0529]]),
+            coeffs([[0.1787, -1.4630], [-0.3554, 0.4275], [-0.0227, 5.3251],
+                    [0.1206, -2.5771], [-0.0670, 0.3703]]),
+        ];
+        let cos_s = F::cos(theta_s);
+        let zenith = A3(zenith_x / perez(&perez_c[0], 1., cos_s),
+                        zenith_y / perez(&perez_c[1], 1., cos_s),
+                        zenith_lum / perez(&perez_c[2], 1., cos_s));
+
+        let mut sky = Self { sun, perez: perez_c, zenith, ground: Color::ZERO, scale: sc.scale,
+                             light_group: sc.light_group, dist: EnvDist::uniform(0.) };
+
+        // the ground diffusely reflects the sky's irradiance on a horizontal plane
+        let cell = |p: I2| (p.map(F::of) + 0.5) / SKY_DIMS.map(F::of);
+        let d_omega = F::PI * F::TWO_PI / F::of(SKY_DIMS.product());
+        let irradiance = Rect::at_origin(SKY_DIMS).positions().map(|p| {
+            let d = conv!(EnvDist::dir(cell(p)) => F3);
+            if d[Y] <= 0. { Color::ZERO }
+            else { sky.sky(d) * (d[Y] * F::sin(cell(p)[X] * F::PI) * d_omega) }
+        }).sum::<Color>();
+        sky.ground = sc.ground_albedo * irradiance * F::INV_PI;
+
+        sky.dist = EnvDist::new(SKY_DIMS,
+                                |p| sky.radiance(conv!(EnvDist::dir(cell(p)) => F3)).luminance());
+        sky
+    }
+}
+
+impl From<SunSkyConfig> for Sun {
+    fn from(sc: SunSkyConfig) -> Self {
+        let sun = sc.sun();
+        let tr = sun_transmittance(sun[Y], sc.turbidity);
+        Self { sun, cos_max: F::cos(sc.sun_radius.to_radians()),
+               radiance: Rgb(tr) * (SUN_LUMINANCE * sc.scale),
+               light_group: sc.light_group }
+    }
+}
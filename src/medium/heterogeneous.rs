@@ -0,0 +1,128 @@
+use std::convert::TryFrom;
+use std::fs;
+
+#[allow(clippy::wildcard_imports)]
+use graphite::*;
+use serde::Deserialize;
+
+use crate::color::Color;
+use crate::sampler::Sampler;
+use crate::util::config;
+
+#[derive(Debug, Deserialize)]
+pub struct Heterogeneous {
+    density: Grid,
+    sigma_t: F,
+    albedo:  Color,
+}
+
+impl Heterogeneous {
+    // delta tracking
+    #[inline] pub fn sample(&self, ray: &R, sampler: &mut Sampler) -> (Color, Option<F>) {
+        let (mut t, t_max) = match self.density.clip(ray) { None => return (Color::ONE, None),
+                                                            Some(b) => b };
+        let inv_max = (self.sigma_t * self.density.max).inv();
+        loop {
+            t -= F::ln(1. - sampler.rng()) * inv_max;
+            if t >= t_max { return (Color::ONE, None) }
+            if self.density.eval(ray.at(t).conv()) * self.sigma_t * inv_max > sampler.rng()
+            { return (self.albedo, Some(t)) }
+        }
+    }
+
+    // ratio tracking
+    #[inline] pub fn tr(&self, ray: &R, sampler: &mut Sampler) -> Color {
+        let (mut t, t_max) = match self.density.clip(ray) { None => return Color::ONE,
+                                                            Some(b) => b };
+        let inv_max = (self.sigma_t * self.density.max).inv();
+        let mut tr = 1.;
+        loop {
+            t -= F::ln(1. - sampler.rng()) * inv_max;
+            if t >= t_max { return tr.conv() }
+            tr *= 1. - F::max(0., self.density.eval(ray.at(t).conv()) * self.sigma_t * inv_max);
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(try_from="GridConfig")]
+struct Grid {
+    dims: A3<I>,
+    data: Box<[F]>,
+    lo:   F3,
+    hi:   F3,
+    max:  F,
+}
+
+impl Grid {
+    // (t_min, t_max) of the ray segment overlapping the grid bounds
+    #[inline] fn clip(&self, ray: &R) -> Option<(F, F)> {
+        let o = conv!(ray.o => F3);
+        let d = conv!(ray.d => F3);
+        let t0 = (self.lo - o) / d;
+        let t1 = (self.hi - o) / d;
+        let tn = t0.zip(t1, F::min).fold(0., F::max);
+        let tf = t0.zip(t1, F::max).fold(ray.t, F::min);
+        if tn < tf && self.max > 0. { Some((tn, tf)) } else { None }
+    }
+
+    #[inline] fn at(&self, x: I, y: I, z: I) -> F
+    { self.data[usize::of((z * self.dims[Y] + y) * self.dims[X] + x)] }
+
+    // trilinearly interpolated density at world-space point
+    #[inline] fn eval(&self, p: F3) -> F {
+        let g = (p - self.lo) / (self.hi - self.lo) * self.dims.map(|d| F::of(d - 1));
+        let A3(gx, gy, gz) = g;
+        let A3(nx, ny, nz) = self.dims;
+        if gx < 0. || gy < 0. || gz < 0. || gx > F::of(nx - 1) || gy > F::of(ny - 1)
+                                         || gz > F::of(nz - 1) { return 0. }
+        let A3(x0, y0, z0) = g.map(F::floori);
+        let (x1, y1, z1) = (I::min(x0 + 1, nx - 1), I::min(y0 + 1, ny - 1), I::min(z0 + 1, nz - 1));
+        let A3(dx, dy, dz) = g - A3(x0, y0, z0).map(F::of);
+        let lerp = |a: F, b: F, t: F| t.mul_add(b - a, a);
+        let y_0 = lerp(lerp(self.at(x0, y0, z0), self.at(x1, y0, z0), dx),
+                       lerp(self.at(x0, y1, z0), self.at(x1, y1, z0), dx), dy);
+        let y_1 = lerp(lerp(self.at(x0, y0, z1), self.at(x1, y0, z1), dx),
+                       lerp(self.at(x0, y1, z1), self.at(x1, y1, z1), dx), dy);
+        lerp(y_0, y_1, dz)
+    }
+}
+
+
+#[derive(Debug, Deserialize)]
+struct GridConfig {
+    src:   String,
+    #[serde(default="GridConfig::default_scale")]
+    scale: F,
+}
+
+impl GridConfig { const fn default_scale() -> F { 1. } }
+
+// Mitsuba volume data format (.vol): single-channel float32 grid
+impl TryFrom<GridConfig> for Grid {
+    type Error = anyhow::Error;
+
+    fn try_from(gc: GridConfig) -> anyhow::Result<Self> {
+        let bytes = fs::read(config::relative_path(gc.src))?;
+        if bytes.len() < 48 || &bytes[..3] != b"VOL" || bytes[3] != 3 {
+            anyhow::bail!("invalid volume grid file");
+        }
+        let word = |i: usize| { let mut w = [0; 4]; w.copy_from_slice(&bytes[i..i + 4]); w };
+        let int = |i| i32::from_le_bytes(word(i));
+        let float = |i| F::from(f32::from_le_bytes(word(i)));
+        if int(4) != 1 { anyhow::bail!("unsupported volume grid encoding (expected float32)"); }
+
+        let dims = A3(int(8), int(12), int(16));
+        let channels = usize::of(int(20));
+        let n = usize::of(dims.product());
+        if channels == 0 || bytes.len() < 48 + 4 * n * channels {
+            anyhow::bail!("truncated volume grid file");
+        }
+        let data = (0..n).map(|i| float(48 + 4 * i * channels) * gc.scale)
+                         .collect::<Vec<_>>().into_boxed_slice();
+        let max = data.iter().copied().fold(0., F::max);
+        Ok(Self { dims, data, max,
+                  lo: A3(float(24), float(28), float(32)),
+                  hi: A3(float(36), float(40), float(44)) })
+    }
+}
@@ -0,0 +1,51 @@
+#[allow(clippy::wildcard_imports)]
+use graphite::*;
+use serde::Deserialize;
+
+use crate::color::{Color, Rgb};
+use crate::sampler::Sampler;
+
+#[derive(Debug, Deserialize)]
+#[serde(from="HomogeneousConfig")]
+pub struct Homogeneous {
+    sigma_s: Color,
+    sigma_t: Color,
+}
+
+impl Homogeneous {
+    #[inline] pub fn sample(&self, ray: &R, sampler: &mut Sampler) -> (Color, Option<F>) {
+        let channel = I::min(F::floori(sampler.rng() * 3.), 2);
+        let sigma = self.sigma_t.0[channel];
+        let dist = if sigma <= 0. { F::POS_INF }
+                   else { -F::ln(1. - sampler.rng()) / sigma };
+        let scattered = dist < ray.t;
+        let tr = transmittance(self.sigma_t, F::min(dist, ray.t));
+        let density = if scattered { self.sigma_t * tr } else { tr };
+        let pdf = density.0.mean();
+        if pdf <= 0. { return (Color::ZERO, None) }
+        if scattered { (tr * self.sigma_s / pdf, Some(dist)) } else { (tr / pdf, None) }
+    }
+
+    #[inline] pub fn tr(&self, ray: &R) -> Color { transmittance(self.sigma_t, ray.t) }
+}
+
+#[inline] fn transmittance(sigma_t: Color, t: F) -> Color
+{ Rgb(sigma_t.0.map(|s| if s <= 0. { 1. } else { F::exp(-s * t) })) }
+
+
+#[derive(Debug, Deserialize)]
+struct HomogeneousConfig {
+    sigma_a: Color,
+    sigma_s: Color,
+    #[serde(default="HomogeneousConfig::default_scale")]
+    scale:   F,
+}
+
+impl HomogeneousConfig { const fn default_scale() -> F { 1. } }
+
+impl From<HomogeneousConfig> for Homogeneous {
+    fn from(hc: HomogeneousConfig) -> Self {
+        let sigma_s = hc.sigma_s * hc.scale;
+        Self { sigma_s, sigma_t: hc.sigma_a * hc.scale + sigma_s }
+    }
+}
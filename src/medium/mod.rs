@@ -0,0 +1,52 @@
+mod heterogeneous;
+mod homogeneous;
+mod phase;
+
+#[allow(clippy::wildcard_imports)]
+use graphite::*;
+use serde::Deserialize;
+
+use crate::color::Color;
+use crate::sampler::Sampler;
+
+use heterogeneous::Heterogeneous;
+use homogeneous::Homogeneous;
+pub use phase::HenyeyGreenstein;
+
+#[derive(Debug, Deserialize)]
+pub struct Medium {
+    #[serde(flatten)]
+        model: Type,
+    #[serde(default)]
+    pub phase: HenyeyGreenstein,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag="type", rename_all="snake_case")]
+enum Type {
+    Heterogeneous(Heterogeneous),
+    Homogeneous(Homogeneous),
+}
+
+impl Medium {
+    // (throughput weight, distance to scattering event if any)
+    #[inline] pub fn sample(&self, ray: &R, sampler: &mut Sampler) -> (Color, Option<F>) {
+        match &self.model {
+            Type::Heterogeneous(m) => m.sample(ray, sampler),
+            Type::Homogeneous(m) => m.sample(ray, sampler),
+        }
+    }
+
+    #[inline] pub fn tr(&self, ray: &R, sampler: &mut Sampler) -> Color {
+        match &self.model {
+            Type::Heterogeneous(m) => m.tr(ray, sampler),
+            Type::Homogeneous(m) => m.tr(ray),
+        }
+    }
+}
+
+impl From<Heterogeneous> for Type
+{ fn from(m: Heterogeneous) -> Self { Self::Heterogeneous(m) } }
+
+impl From<Homogeneous> for Type
+{ fn from(m: Homogeneous) -> Self { Self::Homogeneous(m) } }
@@ -0,0 +1,30 @@
+#[allow(clippy::wildcard_imports)]
+use graphite::*;
+use serde::Deserialize;
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct HenyeyGreenstein {
+    g: F,
+}
+
+impl HenyeyGreenstein {
+    // wo and wi both point away from the scattering point
+    #[inline] pub fn eval(&self, wo: V, wi: V) -> F {
+        let denom = self.g.mul_add(2. * F3::dot(wo.conv(), wi.conv()), self.g.mul_add(self.g, 1.));
+        F::INV_4PI * (1. - self.g.sq()) / (denom * F::sqrt(denom))
+    }
+
+    // (wi, pdf)
+    #[inline] pub fn sample(&self, wo: V, s: F2) -> (V, F) {
+        let ct = if F::abs(self.g) < 1e-3 { s[0].mul_add(-2., 1.) } else {
+            let sq = (1. - self.g.sq()) / (2. * self.g).mul_add(-s[0], 1. + self.g);
+            -(self.g.mul_add(self.g, 1.) - sq.sq()) / (2. * self.g)
+        };
+        let st = F::sqrt(F::max(0., 1. - ct.sq()));
+        let phi = s[1] * F::TWO_PI;
+        let local = conv!(A3(st * F::cos(phi), st * F::sin(phi), ct) => V);
+        let wi = T::from_frame(wo.conv()) * local;
+        (wi, self.eval(wo, wi))
+    }
+}
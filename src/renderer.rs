@@ -1,24 +1,38 @@
+use std::convert::TryFrom;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::thread;
 
 #[allow(clippy::wildcard_imports)]
 use graphite::*;
-use rayon::iter::{ParallelBridge, ParallelIterator};
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
 use serde::{Deserialize, Serialize};
 
-use crate::image::{Image, bitmap::Bitmap};
+use crate::image::{Image, Layer, bitmap::Bitmap, denoise::Denoiser, pixel::Pixel,
+                   rect::Rect};
 use crate::sampler::Sampler;
 use crate::scene::Scene;
-use crate::tracer::Tracer;
+use crate::tracer::{PassState, Technique, Tracer, aov::{self, Aov, Record}};
 use crate::util::progress::Progress;
 
 #[derive(Debug, Deserialize)]
+#[serde(try_from="IntegratorConfig")]
 pub struct Integrator {
-    tracer:  Tracer,
-    sampler: Sampler,
-    scene:   Scene,
-    passes:  I,
+    tracer:   Technique,
+    sampler:  Sampler,
+    scene:    Scene,
+    passes:   I,
+    aovs:     Vec<Aov>,
+    adaptive: Option<Adaptive>,
+    denoise:  Option<Denoiser>,
+}
+
+// concentrate passes on tiles whose relative error is above the threshold
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct Adaptive {
+    threshold:  F,
+    min_passes: I,
 }
 
 pub struct Renderer {
@@ -29,26 +43,45 @@ pub struct Renderer {
 
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
 pub struct RenderState {
-    pub img:  Image,
-    pub pass: I,
+    pub img:     Image,
+    pub pass:    I,
+    #[serde(default)]
+    pub aovs:    Vec<Layer>,
+    // carried across passes by tracers that render whole passes
+    #[serde(default)]
+    pub tracer:  Option<PassState>,
+    // applied when saving; taken from the current scene config, even on resume
+    #[serde(default)]
+    pub denoise: Option<Denoiser>,
 }
 
 impl Renderer {
     pub fn new(running: Arc<AtomicBool>, integrator: Integrator,
                state: Option<RenderState>) -> Self {
-        Self {
-            state: state.unwrap_or_else(|| RenderState {
-                img: Image::new(integrator.scene.camera.resolution),
-                pass: 0,
-            }), integrator, running
-        }
+        let res = integrator.scene.camera.resolution;
+        let mut state = state.unwrap_or_else(|| RenderState {
+            img: Image::new(res),
+            pass: 0,
+            aovs: integrator.aovs.iter().map(|aov| Layer {
+                name: aov.name(),
+                channels: aov.channels().iter().map(|c| (*c).to_string()).collect(),
+                img: Image::new(res),
+            }).collect(),
+            tracer: None,
+            denoise: None,
+        });
+        state.denoise = integrator.denoise;
+        Self { state, integrator, running }
     }
 
     pub fn render(self) -> crossbeam_channel::Receiver<RenderState> {
         let (frame_tx, frame_rx) = crossbeam_channel::unbounded();
 
         thread::spawn(move || {
-            let Integrator { tracer, sampler, scene, passes } = self.integrator;
+            let Integrator { tracer, sampler, scene, passes, aovs, adaptive, .. } = self.integrator;
+            let adaptive = adaptive.filter(|_| {
+                matches!(&tracer, Technique::Sample(t) if !t.splats())
+            });
             let mut state = self.state;
 
             let mut progress = Progress::new("Rendering", state.pass, passes);
@@ -58,20 +91,31 @@ impl Renderer {
                     break
                 }
 
-                state.img += state.img.rect.chunks().par_bridge().map(|rect| {
-                    let mut sampler = sampler.for_rect(state.pass, &rect);
-
-                    Bitmap::from_adhoc(rect, rect.positions().map(|pos| {
-                        sampler.prepare_for_pixel(pos);
+                let tiles = state.img.rect.chunks().filter(|tile| {
+                    adaptive.as_ref().map_or(true, |a| a.is_active(&state.img, tile, state.pass))
+                }).collect::<Vec<_>>();
+                if tiles.is_empty() {
+                    // every tile has converged, so the render is complete
+                    state.pass = passes;
+                    let _ = frame_tx.send(state.clone());
+                    break
+                }
 
-                        let pos = F2::of(pos) + sampler.next_2d();
-                        let ray = scene.camera.ray_at(pos, &mut sampler);
+                // tracer of camera samples for the beauty image, if not rendered in passes
+                let per_sample = match &tracer {
+                    Technique::Sample(t) => Some(t),
+                    Technique::Pass(t) => {
+                        t.pass(&scene, &sampler, state.pass, &mut state.img, &mut state.tracer);
+                        None
+                    }
+                };
 
-                        (pos, tracer.trace(&scene, &mut sampler, ray))
-                    }))
-                }).fold_with(Image::new(scene.camera.resolution), |mut img, b| {
-                    img += b; img
-                }).sum();
+                if per_sample.is_some() || !aovs.is_empty() {
+                    let (img, layers) = sample_pass(per_sample, &aovs, &sampler, &scene, tiles,
+                                                   state.pass);
+                    if per_sample.is_some() { state.img += img; }
+                    state.aovs.iter_mut().zip(layers).for_each(|(l, img)| l.img += img);
+                }
 
                 state.pass += 1;
                 let _ = frame_tx.send(state.clone());
@@ -82,3 +126,81 @@ impl Renderer {
         frame_rx
     }
 }
+
+impl Adaptive {
+    // whether the tile has yet to converge (or has not had enough passes to tell)
+    fn is_active(&self, img: &Image, tile: &Rect, pass: I) -> bool
+    { pass < self.min_passes || tile.positions().any(|pos| img[pos].rel_error() > self.threshold) }
+}
+
+impl Default for Adaptive
+{ fn default() -> Self { Self { threshold: 0.01, min_passes: 16 } } }
+
+// one sample per pixel in the given tiles of the beauty image (if traced per sample)
+// and of each aov
+fn sample_pass(tracer: Option<&Tracer>, aovs: &[Aov], sampler: &Sampler, scene: &Scene,
+               tiles: Vec<Rect>, pass: I) -> (Image, Vec<Image>) {
+    let res = scene.camera.resolution;
+    let empty = || (Image::new(res), vec![Image::new(res); aovs.len()]);
+
+    tiles.into_par_iter().map(|rect| {
+        let mut sampler = sampler.for_rect(pass, &rect);
+        let mut splats = vec![];
+
+        let samples = rect.positions().map(|pos| {
+            sampler.prepare_for_pixel(pos);
+
+            let pos = F2::of(pos) + sampler.next_2d();
+            let ray = scene.camera.ray_at(pos, &mut sampler);
+
+            let mut record = Record::new(aovs);
+            let l = tracer.map(|t| t.trace(scene, &mut sampler, ray, &mut splats, &mut record));
+            (pos, l, aov::eval(aovs, scene, &mut sampler, ray, &record))
+        }).collect::<Vec<_>>();
+
+        let block = Bitmap::from_adhoc(rect, samples.iter().filter_map(|(pos, l, _)| {
+            l.map(|l| (*pos, l))
+        }));
+        let layers = (0..aovs.len()).map(|i| {
+            Bitmap::from_adhoc(rect, samples.iter().map(|(pos, _, a)| (*pos, a[i])))
+        }).collect::<Vec<_>>();
+        (block, layers, splats)
+    }).fold_with(empty(), |(mut img, mut imgs), (block, layers, splats)| {
+        img += block;
+        splats.into_iter().for_each(|(pos, c)| img[pos] += Pixel::splat(c));
+        imgs.iter_mut().zip(layers).for_each(|(img, layer)| *img += layer);
+        (img, imgs)
+    }).reduce(empty, |(mut img, mut imgs), (block, layers)| {
+        img += block;
+        imgs.iter_mut().zip(layers).for_each(|(img, layer)| *img += layer);
+        (img, imgs)
+    })
+}
+
+
+#[derive(Debug, Deserialize)]
+struct IntegratorConfig {
+    tracer:   Technique,
+    sampler:  Sampler,
+    scene:    Scene,
+    passes:   I,
+    #[serde(default)]
+    aovs:     Vec<Aov>,
+    #[serde(default)]
+    adaptive: Option<Adaptive>,
+    #[serde(default)]
+    denoise:  Option<Denoiser>,
+}
+
+impl TryFrom<IntegratorConfig> for Integrator {
+    type Error = anyhow::Error;
+
+    fn try_from(ic: IntegratorConfig) -> anyhow::Result<Self> {
+        let records = matches!(&ic.tracer, Technique::Sample(t) if t.records_radiance());
+        if let Some(aov) = ic.aovs.iter().find(|aov| aov.splits_radiance() && !records) {
+            anyhow::bail!("the {} aov is not produced by the selected tracer", aov.name())
+        }
+        Ok(Self { tracer: ic.tracer, sampler: ic.sampler, scene: ic.scene, passes: ic.passes,
+                  aovs: ic.aovs, adaptive: ic.adaptive, denoise: ic.denoise })
+    }
+}
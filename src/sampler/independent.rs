@@ -21,6 +21,9 @@ impl Independent {
                                               + u64::of(rect.pos[X]))
     }
 
+    #[inline] pub fn for_stream(pass: I, idx: I) -> Self
+    { Self::from_seed(!((u64::of(pass) << 42) + u64::of(idx))) }
+
     #[inline] pub fn next_2d(&mut self) -> F2
     { A2(self.rng(), self.rng()) }
 
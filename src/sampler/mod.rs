@@ -1,4 +1,5 @@
 mod independent;
+mod pss;
 mod rng;
 mod sobol;
 
@@ -9,6 +10,7 @@ use serde::Deserialize;
 use crate::image::rect::Rect;
 
 use independent::Independent;
+pub use pss::Pss;
 use sobol::Sobol;
 
 #[derive(Debug, Deserialize)]
@@ -16,26 +18,41 @@ use sobol::Sobol;
 pub enum Sampler {
     Independent(Independent),
     Sobol(Sobol),
+    #[serde(skip)]
+    Pss(Pss),
 }
 
 impl Sampler {
     #[inline] pub fn for_rect(&self, pass: I, rect: &Rect) -> Self {
         match self {
-            Self::Independent(_) => Independent::for_rect(pass, rect).into(),
+            Self::Independent(_) | Self::Pss(_) => Independent::for_rect(pass, rect).into(),
             Self::Sobol(_) => Sobol::for_rect(pass, rect).into(),
         }
     }
 
+    // independent stream for work not tied to an image region (e.g. light paths)
+    #[inline] pub fn for_stream(pass: I, idx: I) -> Self
+    { Independent::for_stream(pass, idx).into() }
+
     #[inline] pub fn prepare_for_pixel(&mut self, pos: I2) {
         if let Self::Sobol(s) = self {
             s.prepare_for_pixel(pos)
         }
     }
 
+    // steps of the markov chain over primary sample space; other samplers are unaffected
+    #[inline] pub fn start_iteration(&mut self)
+    { if let Self::Pss(s) = self { s.start_iteration() } }
+
+    #[inline] pub fn accept(&mut self) { if let Self::Pss(s) = self { s.accept() } }
+
+    #[inline] pub fn reject(&mut self) { if let Self::Pss(s) = self { s.reject() } }
+
     #[inline] pub fn next_2d(&mut self) -> F2 {
         match self {
             Self::Independent(s) => s.next_2d(),
             Self::Sobol(s) => s.next_2d(),
+            Self::Pss(s) => s.next_2d(),
         }
     }
 
@@ -43,6 +60,7 @@ impl Sampler {
         match self {
             Self::Independent(s) => s.rng(),
             Self::Sobol(s) => s.rng(),
+            Self::Pss(s) => s.rng(),
         }
     }
 }
@@ -62,5 +80,8 @@ impl From<Independent> for Sampler
 impl From<Sobol> for Sampler
 { #[inline] fn from(s: Sobol) -> Self { Self::Sobol(s) } }
 
+impl From<Pss> for Sampler
+{ #[inline] fn from(s: Pss) -> Self { Self::Pss(s) } }
+
 impl Default for Sampler
 { fn default() -> Self { Self::Independent(Independent::default()) } }
@@ -0,0 +1,84 @@
+#[allow(clippy::wildcard_imports)]
+use graphite::*;
+use rand_core::SeedableRng;
+
+use super::rng::{Prng, RandomFloat};
+
+// Kelemen et al. small-step perturbation range
+const S1: F = 1. / 1024.;
+const S2: F = 1. / 64.;
+
+#[derive(Clone, Debug, Default)]
+struct PrimarySample {
+    value:           F,
+    modified:        u64,
+    value_backup:    F,
+    modified_backup: u64,
+}
+
+// primary sample space: a lazily grown vector of uniform samples that is
+// mutated as a whole (large step) or perturbed per dimension (small step)
+#[derive(Clone, Debug)]
+pub struct Pss {
+    rng:        Prng,
+    x:          Vec<PrimarySample>,
+    idx:        usize,
+    iter:       u64,
+    last_large: u64,
+    large:      bool,
+    p_large:    F,
+}
+
+impl Pss {
+    #[inline] pub fn new(seed: u64, p_large: F) -> Self {
+        Self { rng: Prng::seed_from_u64(seed), x: vec![], idx: 0, iter: 0, last_large: 0,
+               large: true, p_large }
+    }
+
+    #[inline] pub fn start_iteration(&mut self) {
+        self.iter += 1;
+        self.large = self.rng.next_f() < self.p_large;
+        self.idx = 0;
+    }
+
+    #[inline] pub fn accept(&mut self) { if self.large { self.last_large = self.iter; } }
+
+    #[inline] pub fn reject(&mut self) {
+        let iter = self.iter;
+        self.x.iter_mut().filter(|xi| xi.modified == iter).for_each(|xi| {
+            xi.value = xi.value_backup;
+            xi.modified = xi.modified_backup;
+        });
+        self.iter -= 1;
+    }
+
+    #[inline] pub fn next_2d(&mut self) -> F2 { A2(self.rng(), self.rng()) }
+
+    #[inline] pub fn rng(&mut self) -> F {
+        if self.idx >= self.x.len() { self.x.push(PrimarySample::default()); }
+        self.mutate(self.idx);
+        self.idx += 1;
+        self.x[self.idx - 1].value
+    }
+
+    // bring a dimension up to date with the current iteration
+    fn mutate(&mut self, i: usize) {
+        let xi = &mut self.x[i];
+        if xi.modified < self.last_large {
+            xi.value = self.rng.next_f();
+            xi.modified = self.last_large;
+        }
+
+        xi.value_backup = xi.value;
+        xi.modified_backup = xi.modified;
+        if self.large { xi.value = self.rng.next_f(); }
+        else {
+            for _ in xi.modified..self.iter {
+                let dv = S2 * F::exp(-F::ln(S2 / S1) * self.rng.next_f());
+                xi.value += if self.rng.next_f() < 0.5 { dv } else { -dv };
+                xi.value -= F::floor(xi.value);
+            }
+        }
+        xi.modified = self.iter;
+    }
+}
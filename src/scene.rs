@@ -7,7 +7,8 @@ use serde::Deserialize;
 use crate::aggregate::bvh::Bvh;
 use crate::camera::Camera;
 use crate::color::Color;
-use crate::light::Light;
+use crate::light::{Light, selection::{Selection, Strategy}};
+use crate::medium::Medium;
 use crate::shape::{Intersectable, Shape, intersection::Its};
 use crate::util::{dpdf::DiscretePdf, pdf::Pdf};
 
@@ -18,28 +19,77 @@ pub struct Scene {
         shapes:      Bvh<Arc<Shape>>,
     pub lights:      Box<[Arc<Light>]>,
         lights_dpdf: DiscretePdf,
-        env:         Option<Arc<Light>>,
+        selection:   Selection,
+        envs:        Box<[usize]>,
+        medium:      Option<Medium>,
+    pub bsphere:     (P, F),
 }
 
 impl Scene {
     #[inline] pub fn intersects(&self, r: R) -> bool { self.shapes.intersects(r) }
     #[inline] pub fn intersect(&self, r: R) -> Option<Its> { self.shapes.intersect(r) }
 
-    #[inline] pub fn sample_random_light(&self, its: &Its, mut s: F2) -> (Pdf<Color>, R) {
-        let (idx, prob) = self.lights_dpdf.sample(&mut s[0]);
-        let (l_light, sray) = self.lights[idx].sample(its, s);
-        (l_light.scale(prob), sray)
+    // pdf includes the probability of selecting the light, except for delta lights
+    #[inline]
+    pub fn sample_random_light(&self, its: &Its, mut s: F2) -> Option<(&Light, Pdf<Color>, R)> {
+        let (idx, prob) = self.selection.sample(its, &mut s[0])?;
+        let light = &*self.lights[idx];
+        let (l_light, sray, _) = light.sample(its, s);
+        let pdf = if light.is_delta() { l_light.pdf } else { l_light.pdf * prob };
+        Some((light, Pdf::new(l_light.val / prob, pdf), sray))
     }
 
+    // emitted radiance at hit, weighted against light-sampling it from its
+    // given the pdf with which the bsdf sampled ray
+    #[inline] pub fn l_emit_mis(&self, its: &Its, hit: &Its, ray: R, pdf: F) -> Color {
+        let l = hit.l_emit_pdf(ray);
+        let prob = hit.shape.0.light_id.map_or(0., |idx| self.selection.pmf(its, idx));
+        l.val * PowerScale::balance2(pdf, l.pdf * prob)
+    }
+
+    // power-proportional choice of a light to emit from
+    #[inline] pub fn sample_light(&self, s: &mut F) -> (&Light, F) {
+        let (idx, prob) = self.lights_dpdf.sample(s);
+        (&self.lights[idx], prob)
+    }
+
+    // probability of selecting a light of the given power in sample_light
+    #[inline] pub fn light_prob(&self, power: F) -> F { power / self.lights_dpdf.total() }
+
+    #[inline] pub fn envs(&self) -> impl Iterator<Item=&Light>
+    { self.envs.iter().map(move |&idx| &*self.lights[idx]) }
+
     #[inline] pub fn lenv(&self, ray: &R) -> Color
-    { self.env.as_ref().map_or(Color::ZERO, |light| light.eval_env(ray)) }
+    { self.envs().map(|light| light.eval_env(ray)).sum() }
+
+    // environment radiance, each light weighted against light-sampling its direction
+    // from its given the pdf with which the bsdf sampled ray
+    #[inline] pub fn lenv_mis(&self, its: &Its, ray: &R, pdf: F) -> Color
+    { self.lenv_mis_each(its, ray, pdf).map(|(_, l)| l).sum() }
+
+    // the terms of lenv_mis, alongside the light each comes from
+    #[inline] pub fn lenv_mis_each<'a>(&'a self, its: &'a Its<'a>, ray: &'a R, pdf: F)
+        -> impl Iterator<Item=(&'a Light, Color)> + 'a
+    {
+        self.envs.iter().map(move |&idx| {
+            let light = &*self.lights[idx];
+            let p = light.pdf_env(ray.d) * self.selection.pmf(its, idx);
+            (light, light.eval_env(ray) * PowerScale::balance2(pdf, p))
+        })
+    }
+
+    #[inline] pub const fn medium(&self) -> Option<&Medium> { self.medium.as_ref() }
 }
 
 
 #[derive(Debug, Deserialize)]
 struct SceneConfig {
-    camera:   Camera,
-    elements: Vec<Element>,
+    camera:         Camera,
+    elements:       Vec<Element>,
+    #[serde(default)]
+    medium:         Option<Medium>,
+    #[serde(default)]
+    light_sampling: Strategy,
 }
 
 #[derive(Debug, Deserialize)]
@@ -55,8 +105,10 @@ impl From<SceneConfig> for Scene {
         let mut lights = vec![];
         for elem in sc.elements {
             match elem {
-                Element::Shape(s) => {
+                Element::Shape(mut s) => {
+                    s.id = I::of(shapes.len());
                     let emitter = s.emission.is_some();
+                    if emitter { s.light_id = Some(lights.len()); }
                     let s = Arc::new(s);
                     shapes.push(s.clone());
                     if emitter { lights.push(Arc::new(s.into())); }
@@ -65,9 +117,16 @@ impl From<SceneConfig> for Scene {
             }
         }
         let shapes = Bvh::new(shapes);
-        let lights_dpdf = DiscretePdf::new(&lights, |light| light.power());
-        let env = lights.iter().find(|light| light.is_env_light()).map(Arc::clone);
+        let bbox = shapes.bbox();
+        let e = bbox.extents();
+        let bsphere = (bbox.center(), 0.5 * F::sqrt(e[X].sq() + e[Y].sq() + e[Z].sq()));
+        lights.iter_mut().filter_map(Arc::get_mut).for_each(|light| light.preprocess(bsphere));
+        let lights_dpdf = DiscretePdf::new(&lights, |light| light.power(bsphere));
+        let (c, h) = (conv!(bbox.center() => F3), A3(e[X], e[Y], e[Z]) * 0.5);
+        let selection = Selection::new(sc.light_sampling, &lights, (c - h, c + h), bsphere);
+        let envs = (0..lights.len()).filter(|&i| lights[i].is_env_light()).collect();
         Self { shapes, camera: sc.camera,
-               lights: lights.into_boxed_slice(), lights_dpdf, env }
+               lights: lights.into_boxed_slice(), lights_dpdf, selection, envs,
+               medium: sc.medium, bsphere }
     }
 }
@@ -5,25 +5,32 @@ use graphite::*;
 
 use crate::bsdf::Bsdf;
 use crate::color::Color;
+use crate::medium::Medium;
 use crate::shape::{self, Intersectable, Shape};
 use crate::util::pdf::Pdf;
 
 pub type ShapeRef<'a> = (&'a Shape, I);
 static SHAPE_REF_PH: ShapeRef = (&shape::PLACEHOLDER, 0);
 
-#[derive(Debug)]
+// offset in texture space of the finite differences of bump maps
+const BUMP_DELTA: F = 5e-4;
+
+#[derive(Clone, Copy, Debug)]
 pub struct Its<'a> {
     pub p:     P,
     pub n:     N,
     pub uv:    F2,
     pub t:     F,
     pub shape: ShapeRef<'a>,
+    // surface derivatives along the texture coordinates, zero where unknown
+    pub dpdu:  V,
+    pub dpdv:  V,
 }
 
 impl<'a> Its<'a> {
     // Constructors
     #[inline] pub const fn its(p: P, n: N, uv: F2, t: F, shape: ShapeRef<'a>) -> Self
-    { Self { p, n, uv, t, shape } }
+    { Self { p, n, uv, t, shape, dpdu: V::ZERO, dpdv: V::ZERO } }
 
     #[inline] pub fn new(p: P, n: N, uv: F2, t: F) -> Self { Self::its(p, n, uv, t, SHAPE_REF_PH) }
 
@@ -36,8 +43,12 @@ impl<'a> Its<'a> {
     #[inline] pub fn with_hit_info(self) -> Self
     { <&'a Shape>::clone(&self.shape.0).hit_info(self) }
 
+    #[inline] pub const fn with_tangents(mut self, dpdu: V, dpdv: V) -> Self
+    { self.dpdu = dpdu; self.dpdv = dpdv; self }
+
     // Generators
-    #[inline] pub fn to_world(&self) -> T { T::from_frame(self.n) }
+    // shading frame about the normal perturbed by the shape's bump and normal maps
+    #[inline] pub fn to_world(&self) -> T { T::from_frame(self.shading_n()) }
 
     #[inline] pub fn spawn_ray(&self, d: V) -> R { R::unbounded(self.p, d) }
 
@@ -46,7 +57,7 @@ impl<'a> Its<'a> {
     #[inline] pub const fn emits(&self) -> bool { self.shape.0.emits() }
 
     #[inline] pub fn l_emit(&self, ray: R) -> Color
-    { if Frame::same_hemisphere(self.n, ray.d) { Color::ZERO }
+    { if !self.shape.0.emits_two_sided() && Frame::same_hemisphere(self.n, ray.d) { Color::ZERO }
       else { self.shape.0.eval(self.uv) } }
 
     #[inline] pub fn l_emit_pdf(&self, ray: R) -> Pdf<Color>
@@ -55,23 +66,99 @@ impl<'a> Its<'a> {
     //// Bsdf Queries
     #[inline] pub const fn bsdf(&self) -> &Bsdf { &self.shape.0.bsdf }
 
-    #[inline] pub fn bsdf_f(&self, wi: V, wo: V) -> Color { self.bsdf().eval(wi, wo, self.uv) }
+    #[inline] pub fn bsdf_f(&self, wi: V, wo: V) -> Color {
+        let r = self.tangent_rotation();
+        self.bsdf().eval(rotate(wi, r), rotate(wo, r), self.uv)
+    }
 
-    #[inline] pub fn bsdf_f_pdf(&self, wi: V, wo: V) -> Pdf<Color>
-    { Pdf::new(self.bsdf_f(wi, wo), self.bsdf().pdf(wi, wo)) }
+    #[inline] pub fn bsdf_pdf(&self, wi: V, wo: V) -> F {
+        let r = self.tangent_rotation();
+        self.bsdf().pdf(rotate(wi, r), rotate(wo, r), self.uv)
+    }
 
-    #[inline] pub fn sample_bsdf(&self, wi: V, s: F2) -> (Pdf<Color>, V, bool)
-    { self.bsdf().sample(wi, self.uv, s) }
+    #[inline] pub fn bsdf_f_pdf(&self, wi: V, wo: V) -> Pdf<Color>
+    { Pdf::new(self.bsdf_f(wi, wo), self.bsdf_pdf(wi, wo)) }
+
+    #[inline] pub fn sample_bsdf(&self, wi: V, s: F2) -> (Pdf<Color>, V, bool) {
+        let A2(c, sin) = self.tangent_rotation();
+        let (f, wo, spec) = self.bsdf().sample(rotate(wi, A2(c, sin)), self.uv, s);
+        (f, rotate(wo, A2(c, -sin)), spec)
+    }
+
+    // (cos, sin) of the rotation about the normal from the frame of to_world to one with its
+    // x axis along dpdu, which only bsdfs that are not symmetric about the normal need
+    #[inline] fn tangent_rotation(&self) -> F2 {
+        if !self.bsdf().is_anisotropic() { return A2(1., 0.) }
+        let t = conv!(self.to_world() / self.dpdu => F3);
+        let l = F::sqrt(t[X].sq() + t[Y].sq());
+        if l > 0. { A2(t[X] / l, -t[Y] / l) } else { A2(1., 0.) }
+    }
+
+    //// Shading Queries
+    #[inline] fn shading_n(&self) -> N {
+        let shape = self.shape.0;
+        if shape.bump_map.is_none() && shape.normal_map.is_none() { return self.n }
+        let (dpdu, dpdv) = (conv!(self.dpdu => F3), conv!(self.dpdv => F3));
+        let (lu, lv) = (F::sqrt(F3::dot(dpdu, dpdu)), F::sqrt(F3::dot(dpdv, dpdv)));
+        if lu <= 0. || lv <= 0. { return self.n }
+        let mut n = unit(conv!(self.n => F3));
+
+        if let Some(bump) = &shape.bump_map {
+            // forward differences, stepping back at the upper edge of the texture
+            let step = |x: F| if x + BUMP_DELTA < 1. { BUMP_DELTA } else { -BUMP_DELTA };
+            let (du, dv) = (step(self.uv[0]), step(self.uv[1]));
+            let h = bump.eval(self.uv);
+            let hu = (bump.eval(self.uv + A2(du, 0.)) - h) / du;
+            let hv = (bump.eval(self.uv + A2(0., dv)) - h) / dv;
+            let (t, b) = tangents(n, dpdu, dpdv);
+            n = unit(n - t * (hu / lu) - b * (hv / lv));
+        }
+
+        if let Some(normal_map) = &shape.normal_map {
+            // tangent space normals stored in [0, 1]
+            let ts = normal_map.eval(self.uv).0.map(|c| c.mul_add(2., -1.));
+            let (t, b) = tangents(n, dpdu, dpdv);
+            n = unit(t * ts[X] + b * ts[Y] + n * ts[Z]);
+        }
+
+        conv!(n => V).conv()
+    }
+
+    //// Medium Queries
+    // medium on the side of the surface that d points into
+    #[inline] pub fn medium_towards(&self, d: V, current: Option<&'a Medium>,
+                                    ambient: Option<&'a Medium>) -> Option<&'a Medium> {
+        let shape = self.shape.0;
+        if !shape.bounds_media() { current }
+        else if Frame::same_hemisphere(self.n, d) { shape.exterior.as_ref().or(ambient) }
+        else { shape.interior.as_ref() }
+    }
 }
 
 impl<'a> Mul<Its<'a>> for T {
     type Output = Its<'a>;
-    #[inline] fn mul(self, Its { p, n, uv, t, shape }: Its) -> Its
-    { Its::its(self * p, self * n, uv, t, shape) }
+    #[inline] fn mul(self, Its { p, n, uv, t, shape, dpdu, dpdv }: Its) -> Its
+    { Its::its(self * p, self * n, uv, t, shape).with_tangents(self * dpdu, self * dpdv) }
 }
 
 impl<'a> Div<Its<'a>> for T {
     type Output = Its<'a>;
-    #[inline] fn div(self, Its { p, n, uv, t, shape }: Its) -> Its
-    { Its::its(self / p, self / n, uv, t, shape) }
+    #[inline] fn div(self, Its { p, n, uv, t, shape, dpdu, dpdv }: Its) -> Its
+    { Its::its(self / p, self / n, uv, t, shape).with_tangents(self / dpdu, self / dpdv) }
 }
+
+// orthonormal tangent and bitangent about unit n, with the tangent along dpdu and the
+// bitangent on the side of dpdv, as the frame may be left-handed where the uvs are mirrored
+#[inline] fn tangents(n: F3, dpdu: F3, dpdv: F3) -> (F3, F3) {
+    let t = unit(dpdu - n * F3::dot(dpdu, n));
+    let b = A3(n[Y] * t[Z] - n[Z] * t[Y], n[Z] * t[X] - n[X] * t[Z], n[X] * t[Y] - n[Y] * t[X]);
+    (t, b * if F3::dot(b, dpdv) < 0. { -1. } else { 1. })
+}
+
+// local direction v rotated about the normal by the angle with (cos, sin) r
+#[inline] fn rotate(v: V, A2(c, s): F2) -> V {
+    let v = conv!(v => F3);
+    conv!(A3(c.mul_add(v[X], -s * v[Y]), s.mul_add(v[X], c * v[Y]), v[Z]) => V)
+}
+
+#[inline] fn unit(v: F3) -> F3 { let l = F::sqrt(F3::dot(v, v)); if l > 0. { v / l } else { v } }
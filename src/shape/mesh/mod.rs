@@ -20,6 +20,56 @@ pub struct Mesh {
     dpdf: DiscretePdf,
 }
 
+impl Mesh {
+    // area-weighted mean of the face normals, widened to contain all of them
+    pub fn normal_cone(&self) -> (F3, F) {
+        let unit = |v: F3| { let l = F::sqrt(F3::dot(v, v)); if l > 0. { v / l } else { v } };
+        let ns = self.tris.elements.iter().map(|t| conv!(t.n() => F3)).collect::<Vec<_>>();
+        let w = unit(ns.iter().fold(F3::ZERO, |acc, &n| acc + n));
+        if w == F3::ZERO { return (A3(0., 0., 1.), -1.) }
+        (w, ns.iter().map(|&n| F3::dot(w, unit(n))).fold(1., F::min))
+    }
+
+    // triangle chosen by area, then sampled as seen from p
+    #[inline] pub fn sample_towards(&self, p: P, mut s: F2) -> (Its, F) {
+        let (idx, prob) = self.dpdf.sample(&mut s[0]);
+        let (its, pdf) = self.tris.elements[idx].sample_towards(p, s);
+        (its.for_idx(idx), pdf * prob)
+    }
+
+    #[inline] pub fn pdf_towards(&self, p: P, its: &Its) -> F {
+        let idx = usize::of(its.shape.1);
+        self.dpdf.prob(idx) * self.tris.elements[idx].pdf_towards(p, its)
+    }
+
+    // traversals skipping triangles whose surface point fails the opacity test
+    #[inline] pub fn intersects_masked(&self, ray: R, opaque: impl Fn(&Its) -> bool) -> bool {
+        self.tris.fold(conv!(ray.d => F3).map(F::is_sign_positive),
+                       false,
+                       |_, node| node.bbox.intersects(ray),
+                       |_, _, tri| {
+                           if tri.intersect(ray).map_or(false, |it| opaque(&tri.hit_info(it))) {
+                               Either::L(true)
+                           } else {
+                               Either::R(false)
+                           }
+                       })
+    }
+
+    #[inline] pub fn intersect_masked(&self, ray: R, opaque: impl Fn(&Its) -> bool) -> Option<Its> {
+        self.tris
+            .fold(conv!(ray.d => F3).map(F::is_sign_positive),
+                  (ray, None),
+                  |(r, _), node| node.bbox.intersects(*r),
+                  |(r, acc), i, tri| Either::R(match tri.intersect(r) {
+                      Some(it) if opaque(&tri.hit_info(it)) =>
+                          (r.clipped(it.t), Some(it.for_idx(i))),
+                      _ => (r, acc),
+                  }))
+            .1
+    }
+}
+
 impl Intersectable for Mesh {
     #[inline] fn bbox(&self) -> BBox { self.tris.bbox() }
 
@@ -6,7 +6,10 @@ use std::sync::Arc;
 use graphite::*;
 use objloader::{Face, MeshData};
 
-use crate::shape::{Intersectable, intersection::Its};
+use crate::shape::{self, Intersectable, intersection::Its};
+
+// range of subtended solid angles over which spherical sampling is worthwhile
+const SPHERICAL_RANGE: (F, F) = (3e-4, 6.22);
 
 pub struct Triangle {
     pub f:         Face,
@@ -36,7 +39,7 @@ impl Triangle {
     #[inline] fn ab(&self) -> V { self.b() - self.a() }
     #[inline] fn ac(&self) -> V { self.c() - self.a() }
 
-    #[inline] fn n(&self) -> V { self.ab() * self.ac() }
+    #[inline] pub fn n(&self) -> V { self.ab() * self.ac() }
 
     #[inline] fn bary(uv: F2) -> F3
     { A3(F::ONE - uv[0] - uv[1], uv[0], uv[1]) }
@@ -51,6 +54,59 @@ impl Triangle {
         (p, n, uv)
     }
 
+    // surface derivatives along the texture coordinates, zero without usable uvs
+    #[inline] fn tangents(&self) -> (V, V) {
+        if self.mesh_data.uv.is_empty() { return (V::ZERO, V::ZERO) }
+        let A3(ta, tb, tc) = self.abct();
+        let (duv02, duv12) = (ta - tc, tb - tc);
+        let (dp02, dp12) = (self.a() - self.c(), self.b() - self.c());
+        let det = duv02[0].mul_add(duv12[1], -duv02[1] * duv12[0]);
+        if F::abs(det) < F::EPS { return (V::ZERO, V::ZERO) }
+        ((dp02 * duv12[1] - dp12 * duv02[1]) / det,
+         (dp12 * duv02[0] - dp02 * duv12[0]) / det)
+    }
+
+    // point sampled uniformly by the solid angle subtended from p, with its pdf;
+    // tiny or huge solid angles fall back to area sampling
+    pub fn sample_towards(&self, p: P, s: F2) -> (Its, F) {
+        let omega = self.solid_angle(p);
+        if omega < SPHERICAL_RANGE.0 || omega > SPHERICAL_RANGE.1 {
+            let its = self.sample_surface(s);
+            let pdf = shape::area_to_solid_angle(self.surface_pdf(), p, &its);
+            return (its, pdf)
+        }
+        let (q, n, uv) = self.eval(self.sample_spherical(p, s));
+        (Its::new(q, n, uv, 0.), omega.inv())
+    }
+
+    pub fn pdf_towards(&self, p: P, its: &Its) -> F {
+        let omega = self.solid_angle(p);
+        if omega < SPHERICAL_RANGE.0 || omega > SPHERICAL_RANGE.1 {
+            shape::area_to_solid_angle(self.surface_pdf(), p, its)
+        } else { omega.inv() }
+    }
+
+    // vertices as unit directions from p
+    #[inline] fn directions(&self, p: P) -> A3<F3>
+    { self.abc().map(|v| conv!((v - p).unit() => F3)) }
+
+    #[inline] fn solid_angle(&self, p: P) -> F { spherical_area(self.directions(p)) }
+
+    // point sampled uniformly by the solid angle subtended from p, as barycentric uv
+    fn sample_spherical(&self, p: P, s: F2) -> F2 {
+        let w = sample_spherical_triangle(self.directions(p), s);
+
+        // barycentrics of where direction w meets the triangle
+        let (e1, e2) = (conv!(self.ab() => F3), conv!(self.ac() => F3));
+        let s1 = cross(w, e2);
+        let div = F3::dot(s1, e1);
+        if F::abs(div) < F::EPS { return A2(1. / 3., 1. / 3.) }
+        let o = conv!(p - self.a() => F3);
+        let A2(u, v) = A2(F3::dot(o, s1), F3::dot(w, cross(o, e1)))
+                           .map(|x| F::clamp(x / div, 0., 1.));
+        if u + v > 1. { A2(u, v) / (u + v) } else { A2(u, v) }
+    }
+
     #[inline] fn intersection_point(&self, ray: R) -> Option<(F, F2)> {
         let pv = ray.d * self.ac();
         let det = F3::dot(self.ab().conv(), pv.conv());
@@ -85,7 +141,8 @@ impl Intersectable for Triangle {
         its.p = p;
         its.n = n;
         its.uv = uv;
-        its
+        let (dpdu, dpdv) = self.tangents();
+        its.with_tangents(dpdu, dpdv)
     }
 
     #[inline] fn sample_surface(&self, s: F2) -> Its {
@@ -100,3 +157,125 @@ impl Intersectable for Triangle {
 
 impl fmt::Debug for Triangle
 { fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { writeln!(f, "T") } }
+
+// van oosterom and strackee's solid angle of a triangle with unit vertices
+#[inline] fn spherical_area(A3(a, b, c): A3<F3>) -> F {
+    let num = F::abs(F3::dot(a, cross(b, c)));
+    let den = 1. + F3::dot(a, b) + F3::dot(b, c) + F3::dot(c, a);
+    F::abs(2. * F::atan2(num, den))
+}
+
+// direction distributed uniformly over a spherical triangle, after arvo 1995
+fn sample_spherical_triangle(A3(a, b, c): A3<F3>, s: F2) -> F3 {
+    let (n_ab, n_bc, n_ca) = (unit(cross(a, b)), unit(cross(b, c)), unit(cross(c, a)));
+    let alpha = angle_between(n_ab, -n_ca);
+    let beta = angle_between(n_bc, -n_ab);
+    let gamma = angle_between(n_ca, -n_bc);
+
+    // sub-triangle with the sampled area fixes the edge towards c'; its angle sum is
+    // offset by pi so that the signs of k1 and k2 match
+    let ap = s[0].mul_add(alpha + beta + gamma - F::PI, F::PI);
+    let (sin_a, cos_a) = (F::sin(alpha), F::cos(alpha));
+    let sin_phi = F::sin(ap).mul_add(cos_a, -F::cos(ap) * sin_a);
+    let cos_phi = F::cos(ap).mul_add(cos_a, F::sin(ap) * sin_a);
+    let k1 = cos_phi + cos_a;
+    let k2 = sin_a.mul_add(-F3::dot(a, b), sin_phi);
+    let cos_bp = F::clamp(k2.mul_add(cos_phi, -k1 * sin_phi).mul_add(cos_a, k2)
+                          / (k2.mul_add(sin_phi, k1 * cos_phi) * sin_a), -1., 1.);
+    let sin_bp = F::sqrt(F::max(0., 1. - cos_bp.sq()));
+    let cp = a * cos_bp + unit(gram_schmidt(c, a)) * sin_bp;
+
+    let ct = 1. - s[1] * (1. - F3::dot(cp, b));
+    let st = F::sqrt(F::max(0., 1. - ct.sq()));
+    b * ct + unit(gram_schmidt(cp, b)) * st
+}
+
+#[inline] fn cross(a: F3, b: F3) -> F3
+{ A3(a[1] * b[2] - a[2] * b[1], a[2] * b[0] - a[0] * b[2], a[0] * b[1] - a[1] * b[0]) }
+
+#[inline] fn unit(v: F3) -> F3 { let l = F::sqrt(F3::dot(v, v)); if l > 0. { v / l } else { v } }
+
+#[inline] fn gram_schmidt(v: F3, w: F3) -> F3 { v - w * F3::dot(v, w) }
+
+// numerically robust angle between unit vectors
+#[inline] fn angle_between(a: F3, b: F3) -> F {
+    let d = |v: F3| F::sqrt(F3::dot(v, v));
+    if F3::dot(a, b) < 0. { F::PI - 2. * F::asin(F::min(1., d(a + b) / 2.)) }
+    else { 2. * F::asin(F::min(1., d(b - a) / 2.)) }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::stratified;
+
+    const N: I = 200;
+
+    // (vertices, viewpoint) subtending solid angles from small to nearly a hemisphere
+    fn configs() -> Vec<(A3<F3>, F3)> {
+        let unit_tri = A3(A3(0., 0., 0.), A3(1., 0., 0.), A3(0., 1., 0.));
+        vec![(unit_tri, A3(0.2, 0.3, 0.5)),
+             (A3(A3(-1., -1., 2.), A3(2., -0.5, 2.5), A3(0.3, 1.5, 1.8)), A3(0., 0., 0.)),
+             (unit_tri, A3(0.6, 0.5, 1.5)),
+             (A3(A3(-10., -10., 1.), A3(10., -10., 1.), A3(0., 10., 1.)), A3(0., 0., 0.))]
+    }
+
+    fn grid() -> impl Iterator<Item=F2> { stratified::grid(N) }
+
+    // share of the grid's points satisfying f
+    fn share(f: impl Fn(F2) -> bool) -> F
+    { F::of(I::of(grid().filter(|&s| f(s)).count())) / F::of(N * N) }
+
+    fn directions(A3(a, b, c): A3<F3>, p: F3) -> A3<F3>
+    { A3(unit(a - p), unit(b - p), unit(c - p)) }
+
+    fn uniform_sphere(s: F2) -> F3 {
+        let z = 1. - 2. * s[0];
+        let r = F::sqrt(F::max(0., 1. - z.sq()));
+        let phi = s[1] * F::TWO_PI;
+        A3(r * F::cos(phi), r * F::sin(phi), z)
+    }
+
+    // whether the ray from o along w meets the triangle, up to a small tolerance
+    fn hits(A3(a, b, c): A3<F3>, o: F3, w: F3) -> bool {
+        let (e1, e2) = (b - a, c - a);
+        let s1 = cross(w, e2);
+        let div = F3::dot(s1, e1);
+        let q = cross(o - a, e1);
+        let (u, v, t) = (F3::dot(o - a, s1) / div, F3::dot(w, q) / div, F3::dot(e2, q) / div);
+        u >= -1e-3 && v >= -1e-3 && u + v <= 1. + 1e-3 && t > 0.
+    }
+
+    #[test]
+    fn spherical_samples_hit_the_triangle() {
+        for (tri, p) in configs() {
+            let dirs = directions(tri, p);
+            assert!(grid().all(|s| hits(tri, p, sample_spherical_triangle(dirs, s))));
+        }
+    }
+
+    #[test]
+    fn spherical_pdf_integrates_to_one() {
+        // the constant pdf 1/omega integrates to one only if omega is the solid angle
+        // covered by the triangle, here estimated from uniform directions on the sphere
+        for (tri, p) in configs() {
+            let estimate = 2. * F::TWO_PI * share(|s| hits(tri, p, uniform_sphere(s)));
+            let omega = spherical_area(directions(tri, p));
+            assert!(F::abs(estimate / omega - 1.) < 0.01, "{} != {}", estimate, omega);
+        }
+    }
+
+    #[test]
+    fn spherical_samples_are_uniform() {
+        // the share of samples in part of the triangle is the share of its solid angle
+        for (tri, p) in configs() {
+            let A3(a, b, c) = tri;
+            let part = A3(a, b, (b + c) * 0.5);
+            let dirs = directions(tri, p);
+            let frac = share(|s| hits(part, p, sample_spherical_triangle(dirs, s)));
+            let expected = spherical_area(directions(part, p)) / spherical_area(dirs);
+            assert!(F::abs(frac - expected) < 0.005, "{} != {}", frac, expected);
+        }
+    }
+}
@@ -12,6 +12,8 @@ use serde::Deserialize;
 
 use crate::bsdf::Bsdf;
 use crate::color::Color;
+use crate::light::Emission;
+use crate::medium::Medium;
 use crate::texture::Tex;
 
 use intersection::Its;
@@ -36,22 +38,92 @@ pub trait Intersectable {
 #[serde(default)]
 pub struct Shape {
     #[serde(flatten)]
-        shape:    Type,
-    pub bsdf:     Bsdf,
-    pub emission: Option<Tex<Color>>,
+        shape:       Type,
+    pub bsdf:        Bsdf,
+    // tangent space normals, and heights in scene units
+    pub normal_map:  Option<Tex<Color>>,
+    pub bump_map:    Option<Tex<F>>,
+    // cutout mask, with surface points below it treated as absent
+    pub alpha:       Option<Tex<F>>,
+    pub alpha_mode:  AlphaMode,
+    pub emission:    Option<Emission>,
+    pub interior:    Option<Medium>,
+    pub exterior:    Option<Medium>,
+    pub light_group: Option<String>,
+    // index among the scene's shapes
+    #[serde(skip)]
+    pub id:          I,
+    // index among the scene's lights, if emissive
+    #[serde(skip)]
+    pub light_id:    Option<usize>,
 }
 
 impl Shape {
     #[inline] pub const fn emits(&self) -> bool { self.emission.is_some() }
+
+    #[inline] pub const fn bounds_media(&self) -> bool
+    { self.interior.is_some() || self.exterior.is_some() }
+
+    // cone bounding the surface normals: (axis, cosine of its half-angle)
+    #[inline] pub fn normal_cone(&self) -> (F3, F) { self.shape.normal_cone() }
+
+    // point on the surface sampled as seen from p, with its solid angle pdf
+    #[inline] pub fn sample_towards(&self, p: P, s: F2) -> (Its, F)
+    { self.shape.sample_towards(p, s) }
+
+    #[inline] pub fn pdf_towards(&self, p: P, its: &Its) -> F { self.shape.pdf_towards(p, its) }
+
+    // whether the alpha mask keeps the surface point its, hit along ray
+    #[inline] fn opaque(&self, its: &Its, ray: R) -> bool {
+        let a = self.alpha.as_ref().map_or(1., |alpha| alpha.eval(its.uv));
+        match self.alpha_mode {
+            AlphaMode::Threshold => a >= 0.5,
+            AlphaMode::Stochastic => a >= 1. || (a > 0. && hash(its.p, ray.d) < a),
+        }
+    }
+}
+
+// deterministic uniform value in [0, 1) for a point and direction, from the bit patterns of
+// their coordinates mixed with the murmur3 finalizer
+#[inline] fn hash(p: P, d: V) -> F {
+    let A3(px, py, pz) = conv!(p => F3 => A3<f32>);
+    let A3(dx, dy, dz) = conv!(d => F3 => A3<f32>);
+    let h = [px, py, pz, dx, dy, dz].iter().fold(0, |h: u64, x| {
+        fmix64(h.wrapping_mul(0x9e37_79b9_7f4a_7c15) ^ u64::from(x.to_bits()))
+    });
+    F::from(f32::from_bits((fmix64(h) >> 41) as u32 | 0x3f80_0000) - 1.)
+}
+
+#[inline] const fn fmix64(mut h: u64) -> u64 {
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    h ^ (h >> 33)
+}
+
+// converts an area density at its to a solid angle density as seen from p
+#[inline] pub fn area_to_solid_angle(pdf: F, p: P, its: &Its) -> F {
+    let d = its.p - p;
+    let d2 = d.norm2();
+    let n = conv!(its.n => F3);
+    let ct = F::abs(F3::dot(n, d.conv())) / F::sqrt(d2 * F3::dot(n, n));
+    if ct <= 0. { 0. } else { pdf * d2 / ct }
 }
 
 impl Intersectable for Shape {
     #[inline] fn bbox(&self) -> BBox { self.shape.bbox() }
 
-    #[inline] fn intersects(&self, ray: R) -> bool { self.shape.intersects(ray) }
+    #[inline] fn intersects(&self, ray: R) -> bool {
+        if self.alpha.is_none() { self.shape.intersects(ray) }
+        else { self.shape.intersects_masked(ray, |its| self.opaque(its, ray)) }
+    }
 
-    #[inline] fn intersect(&self, ray: R) -> Option<Its>
-    { self.shape.intersect(ray).map(|its| its.for_shape(self)) }
+    #[inline] fn intersect(&self, ray: R) -> Option<Its> {
+        let its = if self.alpha.is_none() { self.shape.intersect(ray) }
+                  else { self.shape.intersect_masked(ray, |its| self.opaque(its, ray)) };
+        its.map(|its| its.for_shape(self))
+    }
 
     #[inline] fn hit_info<'a>(&'a self, its: Its<'a>) -> Its<'a> { self.shape.hit_info(its) }
 
@@ -63,7 +135,11 @@ impl Intersectable for Shape {
 }
 
 pub static PLACEHOLDER: Shape = Shape { shape: Type::ZERO, bsdf: Bsdf::ZERO,
-                                        emission: None };
+                                        normal_map: None, bump_map: None,
+                                        alpha: None, alpha_mode: AlphaMode::Threshold,
+                                        emission: None, interior: None,
+                                        exterior: None, light_group: None, id: 0,
+                                        light_id: None };
 
 #[derive(Deserialize)]
 #[serde(tag="type", rename_all="snake_case")]
@@ -73,6 +149,60 @@ enum Type {
     Sphere(Sphere),
 }
 
+impl Type {
+    #[inline] fn normal_cone(&self) -> (F3, F) {
+        match self {
+            Self::None => unreachable!(),
+            Self::Mesh(s) => s.normal_cone(),
+            Self::Sphere(_) => (A3(0., 0., 1.), -1.),
+        }
+    }
+
+    #[inline] fn sample_towards(&self, p: P, s: F2) -> (Its, F) {
+        match self {
+            Self::None => unreachable!(),
+            Self::Mesh(sh) => sh.sample_towards(p, s),
+            Self::Sphere(sh) => sh.sample_towards(p, s),
+        }
+    }
+
+    #[inline] fn pdf_towards(&self, p: P, its: &Its) -> F {
+        match self {
+            Self::None => unreachable!(),
+            Self::Mesh(sh) => sh.pdf_towards(p, its),
+            Self::Sphere(sh) => sh.pdf_towards(p, its),
+        }
+    }
+
+    #[inline] fn intersects_masked(&self, ray: R, opaque: impl Fn(&Its) -> bool) -> bool {
+        match self {
+            Self::None => unreachable!(),
+            Self::Mesh(s) => s.intersects_masked(ray, opaque),
+            Self::Sphere(s) => s.intersect_masked(ray, opaque).is_some(),
+        }
+    }
+
+    #[inline] fn intersect_masked(&self, ray: R, opaque: impl Fn(&Its) -> bool) -> Option<Its> {
+        match self {
+            Self::None => unreachable!(),
+            Self::Mesh(s) => s.intersect_masked(ray, opaque),
+            Self::Sphere(s) => s.intersect_masked(ray, opaque),
+        }
+    }
+}
+
+// how alpha masks decide whether a surface point is present
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(rename_all="snake_case")]
+pub enum AlphaMode {
+    // present where alpha reaches one half
+    Threshold,
+    // present with probability alpha, decided by hashing the hit
+    Stochastic,
+}
+
+impl Default for AlphaMode { fn default() -> Self { Self::Threshold } }
+
 impl Intersectable for Type {
     #[inline] fn bbox(&self) -> BBox {
         match self {
@@ -2,7 +2,7 @@
 use graphite::*;
 use serde::Deserialize;
 
-use crate::shape::{Intersectable, Its};
+use crate::shape::{self, Intersectable, Its};
 
 #[derive(Debug, Deserialize)]
 pub struct Sphere {
@@ -11,16 +11,63 @@ pub struct Sphere {
 }
 
 impl Sphere {
-    #[inline] pub fn intersection_point(&self, ray: R) -> Option<F> {
+    #[inline] fn roots(&self, ray: R) -> Option<F2> {
         let d = ray.o - self.center;
         quad(ray.d.norm2(),
              2. * F3::dot(ray.d.conv(), d.conv()),
              d.norm2() - self.radius.sq())
+    }
+
+    #[inline] pub fn intersection_point(&self, ray: R) -> Option<F> {
+        self.roots(ray)
             .and_then(|t| if ray.range().bounds(t[0]) { Some(t[0]) }
                           else if ray.range().bounds(t[1]) { Some(t[1]) }
                           else { None })
     }
 
+    // nearest intersection whose surface point passes the opacity test
+    #[inline] pub fn intersect_masked(&self, ray: R, opaque: impl Fn(&Its) -> bool) -> Option<Its> {
+        let t = self.roots(ray)?;
+        [t[0], t[1]].iter().copied().filter(|&t| ray.range().bounds(t))
+                    .map(|t| Its::new(ray.at(t), N::ZERO, F2::ZERO, t))
+                    .find(|its| opaque(&self.hit_info(*its)))
+    }
+
+    // point sampled uniformly within the cone the sphere subtends from p, with its
+    // solid angle pdf; falls back to area sampling from inside the sphere
+    #[inline] pub fn sample_towards(&self, p: P, s: F2) -> (Its, F) {
+        let dc2 = (self.center - p).norm2();
+        if dc2 <= self.radius.sq() {
+            let its = self.sample_surface(s);
+            let pdf = shape::area_to_solid_angle(self.surface_pdf(), p, &its);
+            return (its, pdf)
+        }
+
+        let sin2_max = self.radius.sq() / dc2;
+        let ct = 1. - s[0] * Self::one_minus_cos(sin2_max);
+        let st2 = F::max(0., 1. - ct.sq());
+        // angle from the sphere's center between the sampled point and the axis towards p
+        let ca = F::clamp(st2 / F::sqrt(sin2_max)
+                          + ct * F::sqrt(F::max(0., 1. - st2 / sin2_max)), -1., 1.);
+        let sa = F::sqrt(F::max(0., 1. - ca.sq()));
+        let phi = s[1] * F::TWO_PI;
+        let frame = T::from_frame(((self.center - p) / F::sqrt(dc2)).conv());
+        let n = -(frame * conv!(A3(sa * F::cos(phi), sa * F::sin(phi), ca) => V));
+        (Its::new(self.center + n * self.radius, n.conv(), Self::cartesian2uv(n), 0.),
+         (F::TWO_PI * Self::one_minus_cos(sin2_max)).inv())
+    }
+
+    #[inline] pub fn pdf_towards(&self, p: P, its: &Its) -> F {
+        let dc2 = (self.center - p).norm2();
+        if dc2 <= self.radius.sq() {
+            return shape::area_to_solid_angle(self.surface_pdf(), p, its)
+        }
+        (F::TWO_PI * Self::one_minus_cos(self.radius.sq() / dc2)).inv()
+    }
+
+    // stable for the tiny cones subtended by distant spheres
+    #[inline] fn one_minus_cos(sin2: F) -> F { sin2 / (1. + F::sqrt(F::max(0., 1. - sin2))) }
+
     #[inline] fn cartesian2uv<A: Conv<F3>>(x: A) -> F2 {
         let uv = Frame::cart2spher(x);
         A2(uv[X] * F::INV_PI, uv[Y].mul_add(F::INV_2PI, 0.5))
@@ -39,9 +86,15 @@ impl Intersectable for Sphere {
     }
 
     #[inline] fn hit_info<'a>(&'a self, mut its: Its<'a>) -> Its<'a> {
-        its.n = (its.p - self.center).conv();
+        let d = its.p - self.center;
+        its.n = d.conv();
         its.uv = Self::cartesian2uv(its.n);
-        its
+        let A3(x, y, z) = conv!(d => F3);
+        let rho = F::sqrt(x.mul_add(x, y.sq()));
+        if rho <= 0. { return its }
+        // derivatives of the spherical parameterization of cartesian2uv
+        its.with_tangents(conv!(A3(z * x / rho, z * y / rho, -rho) * F::PI => V),
+                          conv!(A3(-y, x, 0.) * F::TWO_PI => V))
     }
 
     #[inline] fn sample_surface(&self, s: F2) -> Its {
@@ -1,12 +1,16 @@
 use std::convert::TryFrom;
+use std::fs::File;
+use std::io::BufReader;
 use std::iter::Sum;
 use std::ops::Mul;
+use std::path::Path;
 
 #[allow(clippy::wildcard_imports)]
 use graphite::*;
-use image::{GenericImageView, Rgba, io::Reader};
+use image::{GenericImageView, codecs::hdr::HdrDecoder, io::Reader};
 use serde::Deserialize;
 
+use crate::color::{Color, Rgb, gamma_correct_inv};
 use crate::image::bitmap::Bitmap;
 use crate::util::config;
 
@@ -22,23 +26,81 @@ impl<A> Bitmap<A> where A: Copy + Zero + Mul<F, Output=A> + Sum<A> {
 
 #[derive(Debug, Deserialize)]
 struct BitmapConfig {
-    src: String,
+    src:        String,
+    #[serde(default)]
+    colorspace: Option<Colorspace>,
 }
 
-impl<A> TryFrom<BitmapConfig> for Bitmap<A> where A: ConvFrom<Rgba<u8>> {
+// encoding of stored values; defaults to linear for float formats and data maps, and to
+// sRGB for 8-bit color images
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all="snake_case")]
+enum Colorspace {
+    Linear,
+    Srgb,
+}
+
+// values a bitmap can hold, along with whether their 8-bit images are sRGB-encoded by default
+pub trait Texel: ConvFrom<Color> { const SRGB: bool; }
+
+impl Texel for F { const SRGB: bool = false; }
+
+impl Texel for Color { const SRGB: bool = true; }
+
+impl<A> TryFrom<BitmapConfig> for Bitmap<A> where A: Texel {
     type Error = anyhow::Error;
 
     fn try_from(bc: BitmapConfig) -> anyhow::Result<Self> {
         let bitmap_path = config::relative_path(bc.src);
-        let image = Reader::open(bitmap_path)?.decode()?;
-        let dims = conv!(image.dimensions() => U2 => I2);
-        let pixels = image.pixels().map(|(_, _, px)| conv!(px => A));
-        Ok(Self::from_seq(dims, pixels))
+        let ext = bitmap_path.extension().and_then(|e| e.to_str())
+                             .map(str::to_ascii_lowercase);
+        let (dims, pixels) = match ext.as_deref() {
+            Some("exr") => load_exr(&bitmap_path)?,
+            Some("hdr") => load_hdr(&bitmap_path)?,
+            _ => load_ldr(&bitmap_path)?,
+        };
+        let float = matches!(ext.as_deref(), Some("exr" | "hdr"));
+        let srgb = bc.colorspace.map_or(A::SRGB && !float, |cs| cs == Colorspace::Srgb);
+        Ok(Self::from_seq(dims, pixels.into_iter().map(|c| {
+            conv!(if srgb { Rgb(c.0.map(gamma_correct_inv)) } else { c } => A)
+        })))
     }
 }
 
+// 8-bit formats, normalized to [0, 1] but not yet decoded
+fn load_ldr(path: &Path) -> anyhow::Result<(I2, Vec<Color>)> {
+    let image = Reader::open(path)?.decode()?;
+    let dims = conv!(image.dimensions() => U2 => I2);
+    let pixels = image.pixels().map(|(_, _, px)| {
+        Rgb(A3(px.0[0], px.0[1], px.0[2]).map(|c| F::of(c) / 255.))
+    }).collect();
+    Ok((dims, pixels))
+}
+
+// Radiance RGBE
+fn load_hdr(path: &Path) -> anyhow::Result<(I2, Vec<Color>)> {
+    let decoder = HdrDecoder::new(BufReader::new(File::open(path)?))?;
+    let meta = decoder.metadata();
+    let dims = conv!((meta.width, meta.height) => U2 => I2);
+    let pixels = decoder.read_image_hdr()?.into_iter()
+                        .map(|px| Rgb(A3(px.0[0], px.0[1], px.0[2]).map(F::from))).collect();
+    Ok((dims, pixels))
+}
+
+// first RGB(A) layer of an OpenEXR image
+fn load_exr(path: &Path) -> anyhow::Result<(I2, Vec<Color>)> {
+    let image = exr::prelude::read_first_rgba_layer_from_file(path,
+        |res, _| (res.width(), vec![Color::ZERO; res.width() * res.height()]),
+        |(w, pixels): &mut (usize, Vec<Color>), pos, (r, g, b, _): (f32, f32, f32, f32)| {
+            pixels[pos.y() * *w + pos.x()] = Rgb(A3(r, g, b).map(F::from));
+        })?;
+    let size = image.layer_data.size;
+    let dims = A2(I::of(size.width()), I::of(size.height()));
+    Ok((dims, image.layer_data.channel_data.pixels.1))
+}
+
 pub fn de_from_config<'de, D, A>(de: D) -> Result<Bitmap<A>, D::Error>
 where D: serde::Deserializer<'de>,
-      A: ConvFrom<Rgba<u8>>
+      A: Texel
 { BitmapConfig::deserialize(de).and_then(|bc| TryFrom::try_from(bc)
                                                       .map_err(serde::de::Error::custom)) }
@@ -4,6 +4,8 @@ use serde::Deserialize;
 #[derive(Clone, Copy, Debug, Default, Deserialize)]
 pub struct Constant<A> { val: A }
 
+impl<A> Constant<A> { #[inline] pub const fn new(val: A) -> Self { Self { val } } }
+
 impl<A: Copy> Constant<A> {
     #[inline] pub fn eval(&self) -> A { self.val }
     #[inline] pub fn mean(&self) -> A { self.val }
@@ -20,7 +20,7 @@ use crate::image::bitmap::Bitmap;
 #[serde(tag="type", rename_all="snake_case")]
 pub enum Tex<A> {
     #[serde(deserialize_with="bitmap::de_from_config")]
-    #[serde(bound(deserialize="A: ConvFrom<image::Rgba<u8>>"))]
+    #[serde(bound(deserialize="A: bitmap::Texel"))]
     Bitmap(Bitmap<A>),
     Checkerboard(Checkerboard<A>),
     Constant(Constant<A>),
@@ -28,6 +28,9 @@ pub enum Tex<A> {
     SmoothGradient(Gradient<A, SmoothScale>),
 }
 
+impl<A> Tex<A>
+{ #[inline] pub const fn constant(val: A) -> Self { Self::Constant(Constant::new(val)) } }
+
 impl<A> Tex<A> where A: Copy + Zero + Add<Output=A> + Mul<F, Output=A> + Sum<A> {
     #[inline] pub fn eval(&self, s: F2) -> A {
         match self {
@@ -65,8 +68,27 @@ impl<A> From<Gradient<A, LinearScale>> for Tex<A> where A: Copy + Add<Output=A>
 impl<A> From<Gradient<A, SmoothScale>> for Tex<A> where A: Copy + Add<Output=A> + Mul<F, Output=A>
 { fn from(t: Gradient<A, SmoothScale>) -> Self { Self::SmoothGradient(t) } }
 
+impl<A> From<TexConfig<A>> for Tex<A> {
+    fn from(tc: TexConfig<A>) -> Self {
+        match tc {
+            TexConfig::Tex(t) => t,
+            TexConfig::Val(val) => Self::constant(val),
+        }
+    }
+}
+
 impl<A> Zero for Tex<A> where A: Zero
 { const ZERO: Self = Self::Constant(Constant::ZERO); }
 
 impl<A> Default for Tex<A> where A: Default
 { fn default() -> Self { Self::Constant(Constant::default()) } }
+
+
+// textures may also be given directly as a constant value
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+#[serde(bound(deserialize="A: Deserialize<'de> + bitmap::Texel"))]
+pub enum TexConfig<A> {
+    Tex(Tex<A>),
+    Val(A),
+}
@@ -0,0 +1,122 @@
+#[allow(clippy::wildcard_imports)]
+use graphite::*;
+use serde::Deserialize;
+
+use crate::color::{Color, Rgb};
+use crate::sampler::Sampler;
+use crate::scene::Scene;
+use crate::shape::intersection::Its;
+
+// arbitrary output variables, evaluated at the first camera hit or split off the tracer's
+// own radiance estimate
+#[derive(Debug, Deserialize)]
+#[serde(tag="type", rename_all="snake_case")]
+pub enum Aov {
+    Albedo,
+    Depth,
+    Direct,
+    Indirect,
+    LightGroup { name: String },
+    Normal,
+    Position,
+    ShapeIndex,
+    Uv,
+}
+
+impl Aov {
+    pub fn name(&self) -> String {
+        match self {
+            Self::Albedo => "albedo".into(),
+            Self::Depth => "depth".into(),
+            Self::Direct => "direct".into(),
+            Self::Indirect => "indirect".into(),
+            Self::LightGroup { name } => format!("light_group_{}", name),
+            Self::Normal => "normal".into(),
+            Self::Position => "position".into(),
+            Self::ShapeIndex => "shape_index".into(),
+            Self::Uv => "uv".into(),
+        }
+    }
+
+    // whether the aov is a part of the radiance that only the tracer can record
+    pub const fn splits_radiance(&self) -> bool
+    { matches!(self, Self::Direct | Self::Indirect | Self::LightGroup { .. }) }
+
+    // names of the leading channels of the color each sample is stored as
+    pub const fn channels(&self) -> &'static [&'static str] {
+        match self {
+            Self::Albedo | Self::Direct | Self::Indirect | Self::LightGroup { .. } =>
+                &["R", "G", "B"],
+            Self::Normal | Self::Position => &["X", "Y", "Z"],
+            Self::Depth => &["Z"],
+            Self::ShapeIndex => &["id"],
+            Self::Uv => &["U", "V"],
+        }
+    }
+}
+
+// radiance of a camera path split up while it is traced, by whether it was scattered
+// towards the camera at the first hit and by the light group it was emitted from
+#[derive(Debug, Default)]
+pub struct Record<'a> {
+    direct:   Color,
+    indirect: Color,
+    groups:   Vec<(&'a str, Color)>,
+}
+
+impl<'a> Record<'a> {
+    pub fn new(aovs: &'a [Aov]) -> Self {
+        let groups = aovs.iter().filter_map(|aov| match aov {
+            Aov::LightGroup { name } => Some((name.as_str(), Color::ZERO)),
+            _ => None,
+        }).collect();
+        Self { groups, ..Self::default() }
+    }
+
+    // l reaching the camera from a path scattered at the given depth
+    #[inline] pub fn add(&mut self, depth: I, l: Color) {
+        if depth == 0 { self.direct += l; } else { self.indirect += l; }
+    }
+
+    #[inline] pub fn add_group(&mut self, group: Option<&str>, l: Color) {
+        if let Some(group) = group {
+            self.groups.iter_mut().filter(|(g, _)| *g == group).for_each(|(_, c)| *c += l);
+        }
+    }
+
+    #[inline] pub fn add_light(&mut self, depth: I, group: Option<&str>, l: Color) {
+        self.add(depth, l);
+        self.add_group(group, l);
+    }
+
+    // whether contributions need splitting by light, which some can only do at extra cost
+    #[inline] pub fn splits_groups(&self) -> bool { !self.groups.is_empty() }
+
+    #[inline] fn group(&self, name: &str) -> Color {
+        self.groups.iter().find(|(g, _)| *g == name).map_or(Color::ZERO, |(_, c)| *c)
+    }
+}
+
+// record holds the tracer's split of its radiance estimate for the same ray
+pub fn eval(aovs: &[Aov], scene: &Scene, sampler: &mut Sampler, ray: R,
+            record: &Record) -> Vec<Color> {
+    let its = scene.intersect(ray);
+    aovs.iter().map(|aov| match (aov, &its) {
+        (Aov::Direct, _) => record.direct,
+        (Aov::Indirect, _) => record.indirect,
+        (Aov::LightGroup { name }, _) => record.group(name),
+        (_, None) => Color::ZERO,
+        (Aov::Albedo, Some(its)) => albedo(its, ray, sampler),
+        (Aov::Depth, Some(its)) => its.t.conv(),
+        (Aov::Normal, Some(its)) => Rgb(conv!(its.n => F3)),
+        (Aov::Position, Some(its)) => Rgb(conv!(its.p => F3)),
+        (Aov::ShapeIndex, Some(its)) => F::of(its.shape.0.id).conv(),
+        (Aov::Uv, Some(its)) => Rgb(F3::a2a(its.uv, 0.)),
+    }).collect()
+}
+
+// single-sample estimate of the directional albedo
+#[inline] fn albedo(its: &Its, ray: R, sampler: &mut Sampler) -> Color {
+    let (bsdf, _, _) = its.sample_bsdf(its.to_world() / -ray.d, sampler.next_2d());
+    if bsdf.pdf > 0. { bsdf.val } else { Color::ZERO }
+}
@@ -0,0 +1,364 @@
+#[allow(clippy::wildcard_imports)]
+use graphite::*;
+use serde::Deserialize;
+
+use crate::color::Color;
+use crate::light::Light;
+use crate::sampler::Sampler;
+use crate::scene::Scene;
+use crate::shape::intersection::Its;
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct Bdpt {
+    depth:     I,
+    heuristic: Heuristic,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(rename_all="snake_case")]
+enum Heuristic {
+    Balance,
+    Power,
+}
+
+#[derive(Clone, Copy)]
+enum Kind<'a> {
+    Camera,
+    Env,
+    Light(&'a Light),
+    // (intersection, world direction towards the previous vertex)
+    Surface(Its<'a>, V),
+}
+
+#[derive(Clone, Copy)]
+struct Vertex<'a> {
+    kind:    Kind<'a>,
+    p:       P,
+    n:       N,
+    tp:      Color,
+    delta:   bool,
+    pdf_fwd: F,
+    pdf_rev: F,
+}
+
+impl Bdpt {
+    #[inline] pub fn trace(&self, scene: &Scene, sampler: &mut Sampler, ray: R,
+                           splats: &mut Vec<(F2, Color)>) -> Color {
+        let camera_path = self.camera_subpath(scene, sampler, ray);
+        let light_path = self.light_subpath(scene, sampler);
+
+        let mut l = Color::ZERO;
+        for t in 1..=camera_path.len() {
+            for s in 0..=light_path.len() {
+                let depth = I::of(t + s) - 2;
+                if (s == 1 && t == 1) || depth < 0 || depth > self.depth { continue }
+                let (c, pos) = self.connect(scene, &light_path, &camera_path, s, t, sampler);
+                if t == 1 { if let Some(pos) = pos { splats.push((pos, c)); } }
+                else { l += c; }
+            }
+        }
+        l
+    }
+
+    fn camera_subpath<'a>(&self, scene: &'a Scene, sampler: &mut Sampler, ray: R)
+        -> Vec<Vertex<'a>> {
+        let mut path = Vec::with_capacity(usize::of(self.depth + 2));
+        path.push(Vertex::new(Kind::Camera, ray.o, N::ZERO, Color::ONE, 0.));
+        let (_, pdf_dir) = scene.camera.pdf_we(&ray);
+        random_walk(scene, sampler, ray, Color::ONE, pdf_dir, self.depth + 1, true, &mut path);
+        path
+    }
+
+    fn light_subpath<'a>(&self, scene: &'a Scene, sampler: &mut Sampler) -> Vec<Vertex<'a>> {
+        let mut path = Vec::with_capacity(usize::of(self.depth + 1));
+        let mut s = sampler.rng();
+        let (light, prob) = scene.sample_light(&mut s);
+        let (le, ray, n, (pdf_pos, pdf_dir)) = light.sample_le(scene.bsphere, sampler.next_2d(),
+                                                                sampler.next_2d());
+        if pdf_pos <= 0. || pdf_dir <= 0. || le == Color::ZERO { return path }
+
+        let kind = if light.is_env_light() { Kind::Env } else { Kind::Light(light) };
+        path.push(Vertex::new(kind, ray.o, n, le, pdf_pos * prob));
+        let ct = if light.is_area() { F::abs(F3::dot(n.conv(), ray.d.conv())) } else { 1. };
+        let tp = le * ct / (prob * pdf_pos * pdf_dir);
+        random_walk(scene, sampler, ray, tp, pdf_dir, self.depth, false, &mut path);
+
+        // the origin of rays from infinity is a position density on the disk they leave
+        if light.is_infinite() {
+            if let Some(v) = path.get_mut(1) {
+                v.pdf_fwd = pdf_pos;
+                if v.on_surface() { v.pdf_fwd *= F::abs(F3::dot(ray.d.conv(), v.n.conv())); }
+            }
+            path[0].pdf_fwd = if light.is_env_light() { env_density(scene, ray.d) } else { prob };
+        }
+        path
+    }
+
+    // (contribution, pixel position for light tracing strategies)
+    fn connect<'a>(&self, scene: &'a Scene, light_path: &[Vertex<'a>],
+                   camera_path: &[Vertex<'a>], s: usize, t: usize, sampler: &mut Sampler)
+        -> (Color, Option<F2>) {
+        let pt = &camera_path[t - 1];
+        if t > 1 && s != 0 && matches!(pt.kind, Kind::Env) { return (Color::ZERO, None) }
+
+        let mut pos = None;
+        let mut sampled = None;
+        let l = if s == 0 {
+            pt.le(scene, &camera_path[t - 2]) * pt.tp
+        } else if t == 1 {
+            let qs = &light_path[s - 1];
+            if !qs.connectible() { return (Color::ZERO, None) }
+            match scene.camera.sample_wi(qs.p, sampler.next_2d()) {
+                Some((we, pdf, lens, p)) if pdf > 0. && we > 0. => {
+                    pos = Some(p);
+                    let v = Vertex::new(Kind::Camera, lens, N::ZERO, Color::ONE * (we / pdf), 0.);
+                    sampled = Some(v);
+                    let l = qs.tp * qs.f(&v) * v.tp;
+                    if l != Color::ZERO && visible(scene, qs.p, lens) { l } else { Color::ZERO }
+                }
+                _ => Color::ZERO,
+            }
+        } else if s == 1 {
+            if !pt.connectible() { return (Color::ZERO, None) }
+            let mut u = sampler.rng();
+            let (light, prob) = scene.sample_light(&mut u);
+            let its = pt.its();
+            let (li, sray, n) = light.sample(&its, sampler.next_2d());
+            if li.pdf <= 0. || li.val == Color::ZERO { Color::ZERO } else {
+                let p = if light.is_infinite() { pt.p + sray.d } else { sray.at(sray.t) };
+                let mut v = Vertex::new(if light.is_env_light() { Kind::Env }
+                                        else { Kind::Light(light) },
+                                        p, n, li.val / prob, 0.);
+                v.pdf_fwd = v.pdf_light_origin(scene, pt);
+                sampled = Some(v);
+                let l = pt.tp * pt.f(&v) * v.tp;
+                if l != Color::ZERO && !scene.intersects(sray) { l } else { Color::ZERO }
+            }
+        } else {
+            let qs = &light_path[s - 1];
+            if !qs.connectible() || !pt.connectible() { return (Color::ZERO, None) }
+            let l = qs.tp * qs.f(pt) * pt.f(qs) * pt.tp;
+            if l != Color::ZERO && visible(scene, qs.p, pt.p) { l / (pt.p - qs.p).norm2() }
+            else { Color::ZERO }
+        };
+
+        if l == Color::ZERO { return (l, pos) }
+        (l * self.mis_weight(scene, light_path, camera_path, sampled, s, t), pos)
+    }
+
+    fn mis_weight<'a>(&self, scene: &Scene, light_path: &[Vertex<'a>],
+                      camera_path: &[Vertex<'a>], sampled: Option<Vertex<'a>>,
+                      s: usize, t: usize) -> F {
+        if s + t == 2 { return 1. }
+
+        let remap = |f: F| if f == 0. { 1. } else {
+            match self.heuristic { Heuristic::Balance => f, Heuristic::Power => f.sq() }
+        };
+
+        let mut lp = light_path[..s].to_vec();
+        let mut cp = camera_path[..t].to_vec();
+        if let Some(v) = sampled { if s == 1 { lp[0] = v; } else if t == 1 { cp[0] = v; } }
+
+        cp[t - 1].delta = false;
+        if s > 0 { lp[s - 1].delta = false; }
+
+        let pt = cp[t - 1];
+        let pt_minus = if t > 1 { Some(cp[t - 2]) } else { None };
+        let qs = if s > 0 { Some(lp[s - 1]) } else { None };
+        let qs_minus = if s > 1 { Some(lp[s - 2]) } else { None };
+
+        cp[t - 1].pdf_rev = match &qs {
+            Some(qs) => qs.pdf(scene, qs_minus.as_ref(), &pt),
+            None => pt_minus.map_or(0., |v| pt.pdf_light_origin(scene, &v)),
+        };
+        if let Some(pt_minus) = &pt_minus {
+            cp[t - 2].pdf_rev = match &qs {
+                Some(qs) => pt.pdf(scene, Some(qs), pt_minus),
+                None => pt.pdf_light(scene, pt_minus),
+            };
+        }
+        if let Some(qs) = &qs { lp[s - 1].pdf_rev = pt.pdf(scene, pt_minus.as_ref(), qs); }
+        if let (Some(qs), Some(qs_minus)) = (&qs, &qs_minus)
+        { lp[s - 2].pdf_rev = qs.pdf(scene, Some(&pt), qs_minus); }
+
+        let mut sum_ri = 0.;
+        let mut ri = 1.;
+        for i in (1..t).rev() {
+            ri *= remap(cp[i].pdf_rev) / remap(cp[i].pdf_fwd);
+            if !cp[i].delta && !cp[i - 1].delta { sum_ri += ri; }
+        }
+        ri = 1.;
+        for i in (0..s).rev() {
+            ri *= remap(lp[i].pdf_rev) / remap(lp[i].pdf_fwd);
+            let delta_light = if i > 0 { lp[i - 1].delta } else { lp[0].is_delta_light() };
+            if !lp[i].delta && !delta_light { sum_ri += ri; }
+        }
+        (1. + sum_ri).inv()
+    }
+}
+
+impl Default for Bdpt
+{ fn default() -> Self { Self { depth: 10, heuristic: Heuristic::Power } } }
+
+#[allow(clippy::too_many_arguments)]
+fn random_walk<'a>(scene: &'a Scene, sampler: &mut Sampler, mut ray: R, mut tp: Color,
+                   mut pdf_fwd: F, depth: I, radiance: bool, path: &mut Vec<Vertex<'a>>) {
+    for _ in 0..depth {
+        let prev = path.len() - 1;
+        let its = match scene.intersect(ray) {
+            None => {
+                if radiance {
+                    path.push(Vertex::new(Kind::Env, ray.o + ray.d, N::ZERO, tp, pdf_fwd));
+                }
+                break
+            }
+            Some(its) => its,
+        };
+
+        let wo = -ray.d;
+        let mut v = Vertex::new(Kind::Surface(its, wo), its.p, its.n, tp, 0.);
+        v.pdf_fwd = path[prev].convert_density(pdf_fwd, &v);
+
+        let frame = its.to_world();
+        let wo_l = frame / wo;
+        let (bsdf, wi_l, spec) = its.sample_bsdf(wo_l, sampler.next_2d());
+        if bsdf.pdf <= 0. || bsdf.val == Color::ZERO { path.push(v); break }
+
+        pdf_fwd = bsdf.pdf;
+        let mut pdf_rev = its.bsdf_pdf(wi_l, wo_l);
+        if spec { v.delta = true; pdf_fwd = 0.; pdf_rev = 0.; }
+        tp *= bsdf.val;
+        ray = its.spawn_ray(frame * wi_l);
+
+        path[prev].pdf_rev = v.convert_density(pdf_rev, &path[prev]);
+        path.push(v);
+    }
+}
+
+impl<'a> Vertex<'a> {
+    #[inline] const fn new(kind: Kind<'a>, p: P, n: N, tp: Color, pdf_fwd: F) -> Self
+    { Self { kind, p, n, tp, delta: false, pdf_fwd, pdf_rev: 0. } }
+
+    #[inline] fn its(&self) -> Its<'a> {
+        match self.kind {
+            Kind::Surface(its, _) => its,
+            _ => Its::new(self.p, self.n, F2::ZERO, 0.),
+        }
+    }
+
+    #[inline] fn on_surface(&self) -> bool {
+        match self.kind {
+            Kind::Surface(..) => true,
+            Kind::Light(l) => l.is_area(),
+            _ => false,
+        }
+    }
+
+    #[inline] fn connectible(&self) -> bool {
+        match self.kind {
+            Kind::Surface(its, _) => !its.bsdf().is_delta(),
+            _ => true,
+        }
+    }
+
+    #[inline] fn is_delta_light(&self) -> bool
+    { matches!(self.kind, Kind::Light(l) if l.is_delta()) }
+
+    // whether self is only a direction, so that no distance to it is meaningful
+    #[inline] fn is_infinite_light(&self) -> bool {
+        match self.kind {
+            Kind::Env => true,
+            Kind::Light(l) => l.is_infinite(),
+            _ => false,
+        }
+    }
+
+    // bsdf * cos(theta) towards next
+    #[inline] fn f(&self, next: &Self) -> Color {
+        match self.kind {
+            Kind::Surface(its, wo) => {
+                let frame = its.to_world();
+                its.bsdf_f(frame / wo, frame / (next.p - self.p).unit())
+            }
+            _ => Color::ZERO,
+        }
+    }
+
+    // emitted radiance towards prev
+    #[inline] fn le(&self, scene: &Scene, prev: &Self) -> Color {
+        match self.kind {
+            Kind::Env => scene.lenv(&R::unbounded(prev.p, self.p - prev.p)),
+            Kind::Surface(its, _) if its.emits() => its.l_emit(R::p2(prev.p, self.p)),
+            _ => Color::ZERO,
+        }
+    }
+
+    // converts a solid angle density at self to an area density at next
+    #[inline] fn convert_density(&self, pdf: F, next: &Self) -> F {
+        if next.is_infinite_light() { return pdf }
+        let w = next.p - self.p;
+        let inv_d2 = w.norm2().inv();
+        let ct = if next.on_surface() {
+            F::abs(F3::dot(next.n.conv(), (w * F::sqrt(inv_d2)).conv()))
+        } else { 1. };
+        pdf * ct * inv_d2
+    }
+
+    // area density of sampling next from self, having arrived from prev
+    #[inline] fn pdf(&self, scene: &Scene, prev: Option<&Self>, next: &Self) -> F {
+        let wn = (next.p - self.p).unit();
+        let pdf = match self.kind {
+            Kind::Env | Kind::Light(_) => return self.pdf_light(scene, next),
+            Kind::Camera => scene.camera.pdf_we(&R::unbounded(self.p, wn)).1,
+            Kind::Surface(its, wo) => {
+                let frame = its.to_world();
+                let wp = prev.map_or(wo, |prev| (prev.p - self.p).unit());
+                its.bsdf_pdf(frame / wp, frame / wn)
+            }
+        };
+        self.convert_density(pdf, next)
+    }
+
+    // area density of emitting towards next from self as a light; lights at infinity
+    // emit from a disk facing the scene, so theirs is the density on that disk
+    #[inline] fn pdf_light(&self, scene: &Scene, next: &Self) -> F {
+        let w = next.p - self.p;
+        let inv_d2 = w.norm2().inv();
+        let w = w * F::sqrt(inv_d2);
+        let ray = R::unbounded(self.p, w);
+        let pdf = match self.kind {
+            Kind::Env => (F::PI * scene.bsphere.1.sq()).inv(),
+            Kind::Light(l) if l.is_infinite() => l.pdf_le(scene.bsphere, &ray, self.n).0,
+            Kind::Light(l) => l.pdf_le(scene.bsphere, &ray, self.n).1 * inv_d2,
+            Kind::Surface(its, _) => its.shape.0.pdf_le(&ray, self.n).1 * inv_d2,
+            Kind::Camera => 0.,
+        };
+        if next.on_surface() { pdf * F::abs(F3::dot(next.n.conv(), w.conv())) } else { pdf }
+    }
+
+    // density of self being chosen as a light subpath origin, which for lights at infinity
+    // is that of their direction, their only emitted one for directional lights
+    #[inline] fn pdf_light_origin(&self, scene: &Scene, next: &Self) -> F {
+        let w = (next.p - self.p).unit();
+        match self.kind {
+            Kind::Env => env_density(scene, -w),
+            Kind::Light(l) if l.is_infinite() => scene.light_prob(l.power(scene.bsphere)),
+            Kind::Light(l) => scene.light_prob(l.power(scene.bsphere))
+                              * l.pdf_le(scene.bsphere, &R::unbounded(self.p, w), self.n).0,
+            Kind::Surface(its, _) => {
+                let shape = its.shape.0;
+                scene.light_prob(shape.power()) * shape.pdf_le(&R::unbounded(self.p, w), self.n).0
+            }
+            Kind::Camera => 0.,
+        }
+    }
+}
+
+// density of sampling direction d towards the environment lights
+#[inline] fn env_density(scene: &Scene, d: V) -> F
+{
+    scene.envs().map(|env| scene.light_prob(env.power(scene.bsphere)) * env.pdf_env(d)).sum()
+}
+
+#[inline] fn visible(scene: &Scene, a: P, b: P) -> bool { !scene.intersects(R::p2(a, b)) }
@@ -2,22 +2,29 @@
 use graphite::*;
 
 use crate::color::Color;
+use crate::light::Light;
 use crate::sampler::Sampler;
 use crate::scene::Scene;
 use crate::shape::intersection::Its;
-use crate::util::pdf::Pdf;
 
-#[inline] pub fn l_light(scene: &Scene, its: &Its, wo: V, frame: T, s: F2) -> Color {
+// along with the light sampled, if any
+#[inline] pub fn l_light<'a>(scene: &'a Scene, its: &Its, wo: V, frame: T, s: F2)
+    -> (Color, Option<&'a Light>)
+{
     if !its.bsdf().is_delta() {
-        let (light, sray) = scene.sample_random_light(its, s);
+        let (emitter, light, sray) = match scene.sample_random_light(its, s) {
+            Some(sample) => sample,
+            None => return (Color::ZERO, None),
+        };
         if light.pdf > 0. && light.val != Color::ZERO && !scene.intersects(sray) {
             let bsdf = its.bsdf_f_pdf(wo, frame / sray.d);
             if bsdf.pdf > 0. && bsdf.val != Color::ZERO {
-                return light.val * bsdf.val * PowerScale::balance2(light.pdf, bsdf.pdf);
+                let l = light.val * bsdf.val * PowerScale::balance2(light.pdf, bsdf.pdf);
+                return (l, Some(emitter))
             }
         }
     }
-    Color::ZERO
+    (Color::ZERO, None)
 }
 
 #[derive(Debug)]
@@ -27,6 +34,8 @@ pub struct BounceInfo<'a> {
     pub its:  Option<Its<'a>>,
     pub ray:  R,
     pub spec: bool,
+    // of sampling ray from the bsdf
+    pub pdf:  F,
 }
 
 #[inline]
@@ -34,16 +43,15 @@ pub fn l_mat<'a>(scene: &'a Scene, its: &Its, wo: V, frame: T, s: F2) -> Option<
     let (bsdf, wi, spec) = its.sample_bsdf(wo, s);
     if bsdf.pdf > 0. && bsdf.val != Color::ZERO {
         let ray = its.spawn_ray(frame * wi);
-        let its = scene.intersect(ray);
-        let light = match &its {
-            None => Pdf::sole(scene.lenv(&ray)),
-            Some(its) if its.emits() => its.l_emit_pdf(ray),
-            _ => Pdf::ZERO,
+        let hit = scene.intersect(ray);
+        let l = if spec { Color::ZERO } else {
+            bsdf.val * match &hit {
+                None => scene.lenv_mis(its, &ray, bsdf.pdf),
+                Some(hit) if hit.emits() => scene.l_emit_mis(its, hit, ray, bsdf.pdf),
+                _ => Color::ZERO,
+            }
         };
-        let l = if light.pdf > 0. && light.val != Color::ZERO && !spec {
-            bsdf.val * light.val * PowerScale::balance2(bsdf.pdf, light.pdf)
-        } else { Color::ZERO };
-        Some(BounceInfo { l, tp: bsdf.val, its, ray, spec })
+        Some(BounceInfo { l, tp: bsdf.val, its: hit, ray, spec, pdf: bsdf.pdf })
     } else { None }
 }
 
@@ -52,7 +60,7 @@ pub fn l_mat<'a>(scene: &'a Scene, its: &Its, wo: V, frame: T, s: F2) -> Option<
         let frame = its.to_world();
         let wo = frame / -ray.d;
 
-        its.l_emit(ray) + l_light(scene, &its, wo, frame, sampler.next_2d())
+        its.l_emit(ray) + l_light(scene, &its, wo, frame, sampler.next_2d()).0
                         + l_mat(scene, &its, wo, frame, sampler.next_2d())
                             .map_or(Color::ZERO, |bounce| bounce.l)
     })
@@ -0,0 +1,86 @@
+#[allow(clippy::wildcard_imports)]
+use graphite::*;
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use serde::Deserialize;
+
+use crate::color::Color;
+use crate::image::{Image, bitmap::Bitmap, pixel::Pixel};
+use crate::sampler::{Pss, Sampler};
+use crate::scene::Scene;
+use crate::util::dpdf::DiscretePdf;
+
+use super::path::Path;
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct Mlt {
+    path:      Path,
+    bootstrap: I,
+    chains:    I,
+    mutations: I,
+    p_large:   F,
+}
+
+impl Mlt {
+    // one pass of `mutations` per pixel spread over all chains, normalized by
+    // a fresh bootstrap estimate so that each pass is an independent estimate
+    pub fn pass(&self, scene: &Scene, pass: I) -> Image {
+        let res = scene.camera.resolution;
+        let seed = |i: I| (u64::of(pass) << 32) + u64::of(i);
+
+        let weights = (0..self.bootstrap).into_par_iter().map(|i| {
+            let mut sampler = Pss::new(seed(i), self.p_large).into();
+            self.eval(scene, &mut sampler).1.luminance()
+        }).collect::<Vec<_>>();
+        let b = weights.iter().sum::<F>() / F::of(self.bootstrap);
+
+        let mut img = Bitmap::from_seq(res, (0..res.product())
+                                                .map(|_| Pixel::new(Color::ZERO, 1.)));
+        if b <= 0. { return img }
+
+        let dpdf = DiscretePdf::new(&weights, |&w| w);
+        let per_chain = (self.mutations * res.product() + self.chains - 1) / self.chains;
+        let scale = b * F::of(res.product()) / F::of(per_chain * self.chains);
+
+        img += (0..self.chains).into_par_iter().fold(|| Image::new(res), |mut img, c| {
+            let mut rng = Sampler::for_stream(pass, c);
+            let mut u = rng.rng();
+            let (idx, _) = dpdf.sample(&mut u);
+
+            let mut sampler: Sampler = Pss::new(seed(I::of(idx)), self.p_large).into();
+            let (mut pos, mut l) = self.eval(scene, &mut sampler);
+
+            for _ in 0..per_chain {
+                sampler.start_iteration();
+                let (pos_p, l_p) = self.eval(scene, &mut sampler);
+                let (y, y_p) = (l.luminance(), l_p.luminance());
+                let a = if y > 0. { F::min(1., y_p / y) } else { 1. };
+
+                // expected-value splatting of both the current and proposed states
+                if y_p > 0. { img[pos_p] += Pixel::splat(l_p * (a * scale / y_p)); }
+                if y > 0. { img[pos] += Pixel::splat(l * ((1. - a) * scale / y)); }
+
+                if rng.rng() < a {
+                    sampler.accept();
+                    pos = pos_p;
+                    l = l_p;
+                } else { sampler.reject(); }
+            }
+            img
+        }).sum();
+        img
+    }
+
+    #[inline] fn eval(&self, scene: &Scene, sampler: &mut Sampler) -> (F2, Color) {
+        let pos = sampler.next_2d() * F2::of(scene.camera.resolution);
+        let ray = scene.camera.ray_at(pos, sampler);
+        (pos, self.path.trace(scene, sampler, ray, None))
+    }
+}
+
+impl Default for Mlt {
+    fn default() -> Self {
+        Self { path: Path::default(), bootstrap: 100_000, chains: 1000, mutations: 1,
+               p_large: 0.3 }
+    }
+}
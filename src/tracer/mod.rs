@@ -1,39 +1,98 @@
 mod ao;
+pub mod aov;
+mod bdpt;
 mod direct;
+mod mlt;
 mod normals;
 mod path;
 mod silhouette;
+mod sppm;
+mod volpath;
 
 #[allow(clippy::wildcard_imports)]
 use graphite::*;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 use crate::color::Color;
+use crate::image::{Image, bitmap::Bitmap};
 use crate::sampler::Sampler;
 use crate::scene::Scene;
 
 use ao::AmbientOcclusion;
+use aov::Record;
+use bdpt::Bdpt;
+use mlt::Mlt;
 use path::Path;
+pub use sppm::{Estimate, Sppm};
+use volpath::Volpath;
 
+// either a tracer of single camera rays or one rendering whole passes over the image
 #[derive(Debug, Deserialize)]
-#[serde(tag="type", rename_all="snake_case")]
+#[serde(from="TracerConfig")]
+pub enum Technique {
+    Sample(Tracer),
+    Pass(PassTracer),
+}
+
+#[derive(Debug)]
 pub enum Tracer {
     AmbientOcclusion(AmbientOcclusion),
+    Bdpt(Bdpt),
     Direct,
     Normals,
     Path(Path),
     Silhouette,
+    Volpath(Volpath),
+}
+
+#[derive(Debug)]
+pub enum PassTracer {
+    Mlt(Mlt),
+    Sppm(Sppm),
+}
+
+// what a pass tracer carries from one pass to the next, persisted with the render state
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag="type", rename_all="snake_case")]
+pub enum PassState {
+    Sppm(Bitmap<Estimate>),
 }
 
 impl Tracer {
-    #[inline]
-    pub fn trace(&self, scene: &Scene, sampler: &mut Sampler, ray: R) -> Color {
+    // whether the tracer contributes to pixels other than the one it traces; these are
+    // normalized by the pass count, so every pixel has to be traced in every pass
+    #[inline] pub const fn splats(&self) -> bool { matches!(self, Self::Bdpt(_)) }
+
+    // whether the tracer can split its estimate into the radiance aovs
+    #[inline] pub const fn records_radiance(&self) -> bool { matches!(self, Self::Path(_)) }
+
+    // splats collects contributions to pixels other than the one being traced, and record
+    // the split of the estimate for the radiance aovs
+    #[inline] pub fn trace(&self, scene: &Scene, sampler: &mut Sampler, ray: R,
+                           splats: &mut Vec<(F2, Color)>, record: &mut Record) -> Color {
         match self {
             Self::AmbientOcclusion(t) => t.trace(scene, sampler, ray),
+            Self::Bdpt(t) => t.trace(scene, sampler, ray, splats),
             Self::Direct => direct::trace(scene, sampler, ray),
             Self::Normals => normals::trace(scene, ray),
-            Self::Path(t) => t.trace(scene, sampler, ray),
+            Self::Path(t) => t.trace(scene, sampler, ray, Some(record)),
             Self::Silhouette => silhouette::trace(scene, ray),
+            Self::Volpath(t) => t.trace(scene, sampler, ray),
+        }
+    }
+}
+
+impl PassTracer {
+    // renders the next pass into img, along with any state the tracer keeps across passes
+    #[inline] pub fn pass(&self, scene: &Scene, sampler: &Sampler, pass: I, img: &mut Image,
+                          state: &mut Option<PassState>) {
+        match self {
+            Self::Mlt(t) => *img += t.pass(scene, pass),
+            Self::Sppm(t) => {
+                let PassState::Sppm(estimates) =
+                    state.get_or_insert_with(|| PassState::Sppm(t.estimates(scene)));
+                *img = t.pass(scene, sampler, pass, estimates);
+            }
         }
     }
 }
@@ -41,5 +100,48 @@ impl Tracer {
 impl From<AmbientOcclusion> for Tracer
 { fn from(t: AmbientOcclusion) -> Self { Self::AmbientOcclusion(t) } }
 
+impl From<Bdpt> for Tracer
+{ fn from(t: Bdpt) -> Self { Self::Bdpt(t) } }
+
+impl From<Mlt> for PassTracer
+{ fn from(t: Mlt) -> Self { Self::Mlt(t) } }
+
 impl From<Path> for Tracer
 { fn from(t: Path) -> Self { Self::Path(t) } }
+
+impl From<Sppm> for PassTracer
+{ fn from(t: Sppm) -> Self { Self::Sppm(t) } }
+
+impl From<Volpath> for Tracer
+{ fn from(t: Volpath) -> Self { Self::Volpath(t) } }
+
+
+#[derive(Debug, Deserialize)]
+#[serde(tag="type", rename_all="snake_case")]
+enum TracerConfig {
+    AmbientOcclusion(AmbientOcclusion),
+    Bdpt(Bdpt),
+    Direct,
+    Mlt(Mlt),
+    Normals,
+    Path(Path),
+    Silhouette,
+    Sppm(Sppm),
+    Volpath(Volpath),
+}
+
+impl From<TracerConfig> for Technique {
+    fn from(tc: TracerConfig) -> Self {
+        match tc {
+            TracerConfig::AmbientOcclusion(t) => Self::Sample(t.into()),
+            TracerConfig::Bdpt(t) => Self::Sample(t.into()),
+            TracerConfig::Direct => Self::Sample(Tracer::Direct),
+            TracerConfig::Mlt(t) => Self::Pass(t.into()),
+            TracerConfig::Normals => Self::Sample(Tracer::Normals),
+            TracerConfig::Path(t) => Self::Sample(t.into()),
+            TracerConfig::Silhouette => Self::Sample(Tracer::Silhouette),
+            TracerConfig::Sppm(t) => Self::Pass(t.into()),
+            TracerConfig::Volpath(t) => Self::Sample(t.into()),
+        }
+    }
+}
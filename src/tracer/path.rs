@@ -3,10 +3,11 @@ use graphite::*;
 use serde::Deserialize;
 
 use crate::color::Color;
+use crate::light::Light;
 use crate::sampler::Sampler;
 use crate::scene::Scene;
 
-use super::direct;
+use super::{aov::Record, direct};
 
 #[derive(Debug, Deserialize)]
 #[serde(default)]
@@ -16,22 +17,50 @@ pub struct Path {
 }
 
 impl Path {
-    #[inline] pub fn trace(&self, scene: &Scene, sampler: &mut Sampler, ray: R) -> Color {
+    // record, if any, collects the split of the estimate into its aovs
+    #[inline] pub fn trace(&self, scene: &Scene, sampler: &mut Sampler, ray: R,
+                           mut record: Option<&mut Record>) -> Color {
         let mut state = direct::BounceInfo { l: Color::ZERO, tp: Color::ONE,
-                                             its: scene.intersect(ray), ray, spec: true };
+                                             its: scene.intersect(ray), ray, spec: true, pdf: 0. };
 
         for depth in 0..self.depth[1] {
             let its = match state.its {
-                None => { state.l += state.tp * scene.lenv(&state.ray); break }
+                None => {
+                    // non-specular bounces already account for the environment in l_mat
+                    if state.spec {
+                        let l = state.tp * scene.lenv(&state.ray);
+                        state.l += l;
+                        if let Some(r) = record.as_deref_mut() {
+                            r.add(depth, l);
+                            if r.splits_groups() {
+                                scene.envs().for_each(|light| {
+                                    r.add_group(light.group(),
+                                                state.tp * light.eval_env(&state.ray));
+                                });
+                            }
+                        }
+                    }
+                    break
+                }
                 Some(its) => its
             };
 
-            if state.spec { state.l += state.tp * its.l_emit(state.ray); }
+            if state.spec {
+                let l = state.tp * its.l_emit(state.ray);
+                state.l += l;
+                if let Some(r) = record.as_deref_mut() {
+                    r.add_light(depth, its.shape.0.light_group.as_deref(), l);
+                }
+            }
 
             let frame = its.to_world();
             let wo = frame / -state.ray.d;
 
-            state.l += state.tp * direct::l_light(scene, &its, wo, frame, sampler.next_2d());
+            let (l, light) = direct::l_light(scene, &its, wo, frame, sampler.next_2d());
+            state.l += state.tp * l;
+            if let Some(r) = record.as_deref_mut() {
+                r.add_light(depth, light.and_then(Light::group), state.tp * l);
+            }
 
             if depth > self.depth[0] {
                 let q = F::min(state.tp.max_channel(), self.rr_tp);
@@ -45,6 +74,21 @@ impl Path {
             };
 
             state.l += state.tp * bounce.l;
+            if let Some(r) = record.as_deref_mut() {
+                r.add(depth, state.tp * bounce.l);
+                match &bounce.its {
+                    _ if bounce.spec => (),
+                    Some(hit) => r.add_group(hit.shape.0.light_group.as_deref(),
+                                             state.tp * bounce.l),
+                    None if r.splits_groups() => {
+                        let tp = state.tp * bounce.tp;
+                        scene.lenv_mis_each(&its, &bounce.ray, bounce.pdf)
+                             .for_each(|(light, l)| r.add_group(light.group(), tp * l));
+                    }
+                    None => (),
+                }
+            }
+
             state.tp *= bounce.tp;
             state.spec = bounce.spec;
             state.ray = bounce.ray;
@@ -0,0 +1,208 @@
+use std::collections::HashMap;
+
+#[allow(clippy::wildcard_imports)]
+use graphite::*;
+use rayon::iter::{IntoParallelIterator, ParallelBridge, ParallelIterator};
+use serde::{Deserialize, Serialize};
+
+use crate::color::Color;
+use crate::image::{Image, bitmap::Bitmap, pixel::Pixel, rect::Rect};
+use crate::sampler::Sampler;
+use crate::scene::Scene;
+use crate::shape::intersection::Its;
+
+use super::direct;
+
+const PHOTON_BATCH: I = 4096;
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct Sppm {
+    depth:   I,
+    photons: I,
+    radius:  F,
+}
+
+// Per-pixel progressive estimate, persisted across passes
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize)]
+pub struct Estimate {
+    ld:  Color,
+    tau: Color,
+    n:   F,
+    r:   F,
+}
+
+struct VisiblePoint<'a> {
+    pos: I2,
+    its: Its<'a>,
+    wo:  V,
+    tp:  Color,
+    r:   F,
+}
+
+struct Grid {
+    cells: HashMap<(I, I, I), Vec<usize>>,
+    size:  F,
+}
+
+impl Sppm {
+    // estimates before the first pass, at the initial radius
+    pub fn estimates(&self, scene: &Scene) -> Bitmap<Estimate> {
+        let rect = Rect::at_origin(scene.camera.resolution);
+        let init = Estimate { r: self.radius, ..Estimate::default() };
+        Bitmap::from_seq(rect.dims, (0..rect.area()).map(|_| init))
+    }
+
+    pub fn pass(&self, scene: &Scene, sampler: &Sampler, pass: I,
+                estimates: &mut Bitmap<Estimate>) -> Image {
+        let rect = Rect::at_origin(scene.camera.resolution);
+
+        // camera pass
+        let camera_points = {
+            let estimates = &*estimates;
+            rect.chunks().par_bridge().map(|rect| {
+                let mut sampler = sampler.for_rect(pass, &rect);
+                rect.positions().map(|pos| {
+                    sampler.prepare_for_pixel(pos);
+                    let ray = scene.camera.ray_at(F2::of(pos) + sampler.next_2d(), &mut sampler);
+                    self.camera_path(scene, &mut sampler, ray, pos, estimates[pos].r)
+                }).collect::<Vec<_>>()
+            }).collect::<Vec<_>>()
+        };
+
+        let mut vps = vec![];
+        for (pos, ld, vp) in camera_points.into_iter().flatten() {
+            estimates[pos].ld += ld;
+            if let Some(vp) = vp { vps.push(vp); }
+        }
+
+        // photon pass
+        let grid = Grid::new(&vps);
+        let batches = (self.photons + PHOTON_BATCH - 1) / PHOTON_BATCH;
+        let photons = (0..batches).into_par_iter().map(|b| {
+            let mut sampler = Sampler::for_stream(pass, b);
+            let mut contribs = vec![];
+            for _ in 0..I::min(PHOTON_BATCH, self.photons - b * PHOTON_BATCH) {
+                self.trace_photon(scene, &mut sampler, &grid, &vps, &mut contribs);
+            }
+            contribs
+        }).collect::<Vec<_>>();
+
+        let mut phi = vec![(Color::ZERO, 0.); vps.len()];
+        for (i, c) in photons.into_iter().flatten() {
+            phi[i].0 += c;
+            phi[i].1 += 1.;
+        }
+
+        // progressive radius reduction
+        for (vp, (phi, m)) in vps.iter().zip(phi) {
+            if m <= 0. { continue }
+            let e = &mut estimates[vp.pos];
+            let n = (2. / 3.).mul_add(m, e.n);
+            let r = e.r * F::sqrt(n / (e.n + m));
+            e.tau = (e.tau + vp.tp * phi) * (r / e.r).sq();
+            e.n = n;
+            e.r = r;
+        }
+
+        let np = F::of(self.photons) * F::PI;
+        let w = F::of(pass + 1);
+        Bitmap::from_seq(rect.dims, estimates.pixels().map(|e| {
+            Pixel::new(e.ld + e.tau / (np * e.r.sq()), w)
+        }))
+    }
+
+    // (pixel position, direct lighting, first non-specular vertex)
+    fn camera_path<'a>(&self, scene: &'a Scene, sampler: &mut Sampler, mut ray: R, pos: I2,
+                       r: F) -> (I2, Color, Option<VisiblePoint<'a>>) {
+        let mut ld = Color::ZERO;
+        let mut tp = Color::ONE;
+        for _ in 0..self.depth {
+            let its = match scene.intersect(ray) {
+                None => { ld += tp * scene.lenv(&ray); break }
+                Some(its) => its,
+            };
+
+            ld += tp * its.l_emit(ray);
+
+            let frame = its.to_world();
+            let wo = frame / -ray.d;
+
+            if !its.bsdf().is_delta() {
+                ld += tp * (direct::l_light(scene, &its, wo, frame, sampler.next_2d()).0
+                            + direct::l_mat(scene, &its, wo, frame, sampler.next_2d())
+                                .map_or(Color::ZERO, |bounce| bounce.l));
+                return (pos, ld, Some(VisiblePoint { pos, its, wo, tp, r }))
+            }
+
+            let (bsdf, wi, _) = its.sample_bsdf(wo, sampler.next_2d());
+            if bsdf.pdf <= 0. || bsdf.val == Color::ZERO { break }
+            tp *= bsdf.val;
+            ray = its.spawn_ray(frame * wi);
+        }
+        (pos, ld, None)
+    }
+
+    fn trace_photon(&self, scene: &Scene, sampler: &mut Sampler, grid: &Grid,
+                    vps: &[VisiblePoint], contribs: &mut Vec<(usize, Color)>) {
+        let mut u = sampler.rng();
+        let (light, prob) = scene.sample_light(&mut u);
+        let (le, mut ray, n, (pdf_pos, pdf_dir)) = light.sample_le(scene.bsphere, sampler.next_2d(),
+                                                                    sampler.next_2d());
+        if pdf_pos <= 0. || pdf_dir <= 0. || le == Color::ZERO { return }
+        let ct = if light.is_area() { F::abs(F3::dot(n.conv(), ray.d.conv())) } else { 1. };
+        let mut tp = le * ct / (prob * pdf_pos * pdf_dir);
+
+        for depth in 0..self.depth {
+            let its = match scene.intersect(ray) { None => break, Some(its) => its };
+
+            if depth > 0 {
+                grid.lookup(its.p, |i| {
+                    let vp = &vps[i];
+                    if (vp.its.p - its.p).norm2() > vp.r.sq() { return }
+                    let wl = vp.its.to_world() / -ray.d;
+                    let ct = F::abs(Frame::ct(wl));
+                    if ct > 0. { contribs.push((i, tp * vp.its.bsdf_f(vp.wo, wl) / ct)); }
+                });
+            }
+
+            let frame = its.to_world();
+            let (bsdf, wi, _) = its.sample_bsdf(frame / -ray.d, sampler.next_2d());
+            if bsdf.pdf <= 0. || bsdf.val == Color::ZERO { break }
+            let tp_new = tp * bsdf.val;
+            // a photon with no remaining throughput carries nothing further
+            let lum = tp.luminance();
+            let q = if lum > 0. { F::max(0., 1. - tp_new.luminance() / lum) } else { 1. };
+            if sampler.rng() < q { break }
+            tp = tp_new / (1. - q);
+            ray = its.spawn_ray(frame * wi);
+        }
+    }
+}
+
+impl Default for Sppm
+{ fn default() -> Self { Self { depth: 10, photons: 100_000, radius: 0.05 } } }
+
+impl Grid {
+    fn new(vps: &[VisiblePoint]) -> Self {
+        let size = 2. * vps.iter().fold(F::EPS, |r, vp| F::max(r, vp.r));
+        let mut grid = Self { cells: HashMap::new(), size };
+        for (i, vp) in vps.iter().enumerate() {
+            let lo = grid.cell(vp.its.p, -vp.r);
+            let hi = grid.cell(vp.its.p, vp.r);
+            for x in lo.0..=hi.0 { for y in lo.1..=hi.1 { for z in lo.2..=hi.2 {
+                grid.cells.entry((x, y, z)).or_insert_with(Vec::new).push(i);
+            } } }
+        }
+        grid
+    }
+
+    #[inline] fn cell(&self, p: P, offset: F) -> (I, I, I) {
+        let c = |d| F::floori((p[d] + offset) / self.size);
+        (c(X), c(Y), c(Z))
+    }
+
+    #[inline] fn lookup(&self, p: P, f: impl FnMut(usize)) {
+        if let Some(vps) = self.cells.get(&self.cell(p, 0.)) { vps.iter().copied().for_each(f); }
+    }
+}
@@ -0,0 +1,147 @@
+#[allow(clippy::wildcard_imports)]
+use graphite::*;
+use serde::Deserialize;
+
+use crate::color::Color;
+use crate::medium::Medium;
+use crate::sampler::Sampler;
+use crate::scene::Scene;
+use crate::shape::intersection::Its;
+use crate::util::pdf::Pdf;
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct Volpath {
+    depth: I2,
+    rr_tp: F,
+}
+
+impl Volpath {
+    #[inline] pub fn trace(&self, scene: &Scene, sampler: &mut Sampler, mut ray: R) -> Color {
+        let mut l = Color::ZERO;
+        let mut tp = Color::ONE;
+        let mut medium = scene.medium();
+        let mut spec = true;
+        let mut pdf = 1.;
+        // last scattering point, from which the light selection pdf is evaluated
+        let mut prev = Its::new(ray.o, N::ZERO, F2::ZERO, 0.);
+
+        for depth in 0..self.depth[1] {
+            let its = scene.intersect(ray);
+
+            let scattered = medium.and_then(|m| {
+                let (mtp, t) = m.sample(&its.as_ref().map_or(ray, |its| ray.clipped(its.t)),
+                                        sampler);
+                tp *= mtp;
+                t.map(|t| (m, ray.at(t)))
+            });
+            if tp == Color::ZERO { break }
+
+            if let Some((m, p)) = scattered {
+                let wo = -ray.d;
+                let phase = |d: V| { let p = m.phase.eval(wo, d); Pdf::new(p.conv(), p) };
+                l += tp * l_light(scene, &Its::new(p, N::ZERO, F2::ZERO, 0.), medium,
+                                  phase, sampler);
+
+                if !self.roulette(depth, &mut tp, sampler) { break }
+
+                let (wi, p_wi) = m.phase.sample(wo, sampler.next_2d());
+                spec = false;
+                pdf = p_wi;
+                prev = Its::new(p, N::ZERO, F2::ZERO, 0.);
+                ray = R::unbounded(p, wi);
+                continue
+            }
+
+            let its = match its {
+                None => {
+                    l += tp * if spec { scene.lenv(&ray) }
+                              else { scene.lenv_mis(&prev, &ray, pdf) };
+                    break
+                }
+                Some(its) if its.bsdf().is_interface() => {
+                    medium = its.medium_towards(ray.d, medium, scene.medium());
+                    ray = its.spawn_ray(ray.d);
+                    continue
+                }
+                Some(its) => its,
+            };
+
+            if its.emits() {
+                l += tp * if spec { its.l_emit(ray) }
+                          else { scene.l_emit_mis(&prev, &its, ray, pdf) };
+            }
+
+            let frame = its.to_world();
+            let wo = frame / -ray.d;
+
+            if !its.bsdf().is_delta() {
+                let bsdf = |d: V| its.bsdf_f_pdf(wo, frame / d);
+                l += tp * l_light(scene, &its, medium, bsdf, sampler);
+            }
+
+            if !self.roulette(depth, &mut tp, sampler) { break }
+
+            let (bsdf, wi, bsdf_spec) = its.sample_bsdf(wo, sampler.next_2d());
+            if bsdf.pdf <= 0. || bsdf.val == Color::ZERO { break }
+
+            tp *= bsdf.val;
+            spec = bsdf_spec;
+            pdf = bsdf.pdf;
+            prev = its;
+            ray = its.spawn_ray(frame * wi);
+            medium = its.medium_towards(ray.d, medium, scene.medium());
+        }
+
+        l
+    }
+
+    #[inline] fn roulette(&self, depth: I, tp: &mut Color, sampler: &mut Sampler) -> bool {
+        if depth > self.depth[0] {
+            let q = F::min(tp.max_channel(), self.rr_tp);
+            if sampler.rng() > q { return false }
+            *tp /= q;
+        }
+        true
+    }
+}
+
+impl Default for Volpath
+{ fn default() -> Self { Self { depth: A2(10, 20), rr_tp: 1. } } }
+
+// next-event estimation with transmittance through participating media
+#[inline] fn l_light<'a>(scene: &'a Scene, its: &Its<'a>, medium: Option<&'a Medium>,
+                         f: impl Fn(V) -> Pdf<Color>, sampler: &mut Sampler) -> Color {
+    let (_, light, sray) = match scene.sample_random_light(its, sampler.next_2d()) {
+        Some(sample) => sample,
+        None => return Color::ZERO,
+    };
+    if light.pdf > 0. && light.val != Color::ZERO {
+        let f = f(sray.d);
+        if f.pdf > 0. && f.val != Color::ZERO {
+            let medium = its.medium_towards(sray.d, medium, scene.medium());
+            let tr = transmittance(scene, sray, medium, sampler);
+            return light.val * f.val * tr * PowerScale::balance2(light.pdf, f.pdf);
+        }
+    }
+    Color::ZERO
+}
+
+#[inline] fn transmittance<'a>(scene: &'a Scene, mut ray: R, mut medium: Option<&'a Medium>,
+                               sampler: &mut Sampler) -> Color {
+    let mut tr = Color::ONE;
+    loop {
+        let its = scene.intersect(ray);
+        if let Some(its) = &its { if !its.bsdf().is_interface() { return Color::ZERO } }
+        if let Some(m) = medium {
+            tr *= m.tr(&its.as_ref().map_or(ray, |its| ray.clipped(its.t)), sampler);
+        }
+        match its {
+            None => return tr,
+            Some(its) => {
+                medium = its.medium_towards(ray.d, medium, scene.medium());
+                ray = R::r(its.p, ray.d, ray.t - its.t);
+            }
+        }
+    }
+}
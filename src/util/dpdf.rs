@@ -37,5 +37,48 @@ impl DiscretePdf {
         (idx, prob)
     }
 
+    #[inline] pub fn prob(&self, idx: usize) -> F { self.cdf[idx + 1] - self.cdf[idx] }
+
+    #[inline] pub fn size(&self) -> I { I::of(self.cdf.len()) - 1 }
+
     #[inline] pub const fn total(&self) -> F { self.total }
 }
+
+// piecewise-constant density over [0, 1]^2 with one cell per grid position
+#[derive(Debug, Default)]
+pub struct DiscretePdf2D {
+    rows:     Box<[DiscretePdf]>,
+    marginal: DiscretePdf,
+}
+
+impl DiscretePdf2D {
+    pub fn new(dims: I2, f: impl Fn(I2) -> F) -> Self {
+        let (rows, weights): (Vec<_>, Vec<_>) = (0..dims[Y]).map(|y| {
+            let row = (0..dims[X]).map(|x| f(A2(x, y))).collect::<Vec<_>>();
+            let total = row.iter().sum::<F>();
+            // rows without any weight are never selected; keep their cdf well-defined
+            if total > 0. { (DiscretePdf::new(&row, |&w| w), total) }
+            else { (DiscretePdf::new(&row, |_| 1.), 0.) }
+        }).unzip();
+        let marginal = if weights.iter().sum::<F>() > 0. { DiscretePdf::new(&weights, |&w| w) }
+                       else { DiscretePdf::new(&weights, |_| 1.) };
+        Self { rows: rows.into_boxed_slice(), marginal }
+    }
+
+    // (continuous sample, density)
+    #[inline] pub fn sample(&self, mut s: F2) -> (F2, F) {
+        let (y, py) = self.marginal.sample(&mut s[Y]);
+        let row = &self.rows[y];
+        let (x, px) = row.sample(&mut s[X]);
+        let n = A2(row.size(), self.marginal.size()).map(F::of);
+        ((A2(I::of(x), I::of(y)).map(F::of) + s) / n, px * py * n.product())
+    }
+
+    #[inline] pub fn pdf(&self, s: F2) -> F {
+        let n = A2(self.rows[0].size(), self.marginal.size());
+        let A2(x, y) = (s * n.map(F::of)).map(F::floori)
+                                        .zip(n, |i, n| I::clamp(i, 0, n - 1))
+                                        .map(usize::of);
+        self.marginal.prob(y) * self.rows[y].prob(x) * F::of(n.product())
+    }
+}
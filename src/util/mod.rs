@@ -3,4 +3,6 @@ pub mod dpdf;
 pub mod either;
 pub mod pdf;
 pub mod progress;
+#[cfg(test)]
+pub mod stratified;
 pub mod vec;
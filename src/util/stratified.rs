@@ -0,0 +1,6 @@
+#[allow(clippy::wildcard_imports)]
+use graphite::*;
+
+// n x n points at the centres of a regular grid over the unit square
+pub fn grid(n: I) -> impl Iterator<Item=F2>
+{ (0..n * n).map(move |i| A2(F::of(i % n) + 0.5, F::of(i / n) + 0.5) / F::of(n)) }